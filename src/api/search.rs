@@ -1,12 +1,156 @@
 use actix_web::{get, web, HttpResponse, Responder};
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::collections::HashSet;
 
+use crate::quickwit::{QUICKWIT_INDEX, QUICKWIT_URL};
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(reqwest::Client::new);
+
+const DEFAULT_HITS: u64 = 20;
+const MAX_HITS: u64 = 100;
+
+/// Full-text search over the crawler's index (`QUICKWIT_INDEX`). Honours the pages' robots directives:
+/// `nosnippet` pages come back without a snippet, `noarchive` pages are marked as not archived
+/// so no cached copy is offered. Near-duplicates (same `cluster_id`) on the requested page are
+/// collapsed into their best-ranked hit unless `collapse=false`; `total` counts uncollapsed hits. `type=Dataset` keeps pages embedding a schema.org
+/// entity of that type.
 #[get("/search")]
 pub async fn search(query: web::Query<SearchQuery>) -> impl Responder {
-    let text = &query.text;
-    HttpResponse::Ok().body(format!("You searched for: {}", text))
+    let text = match query.schema_type.as_deref() {
+        None => query.text.clone(),
+        Some(schema_type) if !schema_type.is_empty() && schema_type.chars().all(|c| c.is_ascii_alphanumeric()) => {
+            format!("({}) AND schema_types:{}", query.text, schema_type)
+        }
+        Some(_) => return HttpResponse::BadRequest().body("type must be a schema.org type name"),
+    };
+    let request = json!({
+        "query": text,
+        "max_hits": query.limit.unwrap_or(DEFAULT_HITS).min(MAX_HITS),
+        "start_offset": query.offset.unwrap_or(0),
+        "snippet_fields": ["meta_description"],
+    });
+
+    let response = CLIENT
+        .post(format!("{}/api/v1/{}/search", QUICKWIT_URL, QUICKWIT_INDEX))
+        .header("Content-Type", "application/json")
+        .body(request.to_string())
+        .send()
+        .await;
+    let body = match response {
+        Ok(resp) if resp.status().is_success() => resp.bytes().await.ok(),
+        Ok(resp) => {
+            tracing::warn!("quickwit search failed: HTTP {}", resp.status());
+            None
+        }
+        Err(err) => {
+            tracing::warn!("quickwit unreachable: {}", err);
+            None
+        }
+    };
+    let Some(parsed) = body.and_then(|b| serde_json::from_slice::<Value>(&b).ok()) else {
+        return HttpResponse::BadGateway().body("search backend unavailable");
+    };
+
+    let no_hits = Vec::new();
+    let hits = parsed["hits"].as_array().unwrap_or(&no_hits);
+    let visible = visible_hits(hits, query.collapse.unwrap_or(true));
+    let collapsed = hits.len() - visible.len();
+    let results: Vec<SearchHit> = visible
+        .into_iter()
+        .map(|i| {
+            let hit = &hits[i];
+            let nosnippet = hit["nosnippet"].as_bool().unwrap_or(false);
+            let snippet = if nosnippet {
+                None
+            } else {
+                parsed["snippets"][i]["meta_description"][0]
+                    .as_str()
+                    .or_else(|| hit["meta_description"].as_str())
+                    .map(|s| s.to_string())
+            };
+
+            SearchHit {
+                url: hit["url"].as_str().unwrap_or_default().to_string(),
+                cluster_id: hit["cluster_id"].as_str().map(|s| s.to_string()),
+                schema_types: hit["schema_types"]
+                    .as_array()
+                    .map(|types| types.iter().filter_map(|t| t.as_str()).map(|t| t.to_string()).collect())
+                    .unwrap_or_default(),
+                title: hit["title"].as_str().map(|s| s.to_string()),
+                crawl_timestamp: hit["crawl_timestamp"].as_i64(),
+                snippet,
+                archived: !hit["noarchive"].as_bool().unwrap_or(false),
+            }
+        })
+        .collect();
+
+    HttpResponse::Ok().json(SearchResults {
+        total: parsed["num_hits"].as_u64().unwrap_or(0),
+        collapsed,
+        hits: results,
+    })
+}
+
+/// Positions of the hits to return: with `collapse`, only the best-ranked hit of each cluster.
+/// Quickwit has no field collapsing, so this works on the requested page of hits alone: a cluster
+/// spread over several pages shows up once on each of them.
+fn visible_hits(hits: &[Value], collapse: bool) -> Vec<usize> {
+    let mut seen_clusters = HashSet::new();
+    (0..hits.len())
+        .filter(|&i| {
+            let hit = &hits[i];
+            let cluster = hit["cluster_id"].as_str().or_else(|| hit["url"].as_str()).unwrap_or_default();
+            !collapse || seen_clusters.insert(cluster.to_string())
+        })
+        .collect()
 }
 
 #[derive(serde::Deserialize)]
 pub struct SearchQuery {
     pub text: String,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+    pub collapse: Option<bool>, // one hit per near-duplicate cluster (default true)
+    #[serde(rename = "type")]
+    pub schema_type: Option<String>, // only pages with a schema.org entity of this type
+}
+
+#[derive(serde::Serialize)]
+pub struct SearchHit {
+    pub url: String,
+    pub cluster_id: Option<String>,
+    pub schema_types: Vec<String>, // types of the page's schema.org entities
+    pub title: Option<String>,
+    pub crawl_timestamp: Option<i64>,
+    pub snippet: Option<String>, // never set for nosnippet pages
+    pub archived: bool,          // false for noarchive pages: no cached copy may be shown
+}
+
+#[derive(serde::Serialize)]
+pub struct SearchResults {
+    pub total: u64,       // matching pages before collapsing; page with `offset` + `limit` over this
+    pub collapsed: usize, // near-duplicates left out of this page
+    pub hits: Vec<SearchHit>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clusters_collapse_within_the_page() {
+        let hits = vec![
+            json!({"url": "https://a.test/1", "cluster_id": "https://a.test/1"}),
+            json!({"url": "https://b.test/1", "cluster_id": "https://a.test/1"}),
+            json!({"url": "https://c.test/1"}),
+            json!({"url": "https://a.test/2", "cluster_id": "https://a.test/2"}),
+            json!({"url": "https://d.test/1", "cluster_id": "https://a.test/2"}),
+        ];
+        assert_eq!(visible_hits(&hits, true), vec![0, 2, 3]);
+        assert_eq!(visible_hits(&hits, false), vec![0, 1, 2, 3, 4]);
+
+        // The next page knows nothing about this one: its first cluster member is shown again
+        assert_eq!(visible_hits(&hits[1..], true), vec![0, 1, 2]);
+    }
 }
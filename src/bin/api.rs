@@ -1,8 +1,9 @@
 use actix_cors::Cors;
-use actix_web::{middleware, web, App, HttpServer};
+use actix_web::{middleware, App, HttpServer};
 use tracing_actix_web::TracingLogger;
-use tracing_subscriber;
 
+#[path = "../common/quickwit.rs"]
+mod quickwit;
 #[path = "../api/search.rs"]
 mod search;
 #[path = "../api/health.rs"]
@@ -1,6 +1,6 @@
 pub mod config;
+mod quickwit;
 pub mod utils;
 
 pub use config::UA;
 pub use config::DOMAINS_SET;
-pub use utils::random_ua;
\ No newline at end of file
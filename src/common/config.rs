@@ -1,5 +1,8 @@
+use once_cell::sync::Lazy;
 use phf::phf_set;
 
+use crate::common::utils::env_or;
+
 pub const DOMAINS_SET: phf::Set<&'static str> = phf_set! {
     "arxiv.org", 
     "github.com", 
@@ -17,6 +20,97 @@ pub const DOMAINS_SET: phf::Set<&'static str> = phf_set! {
     "chemrxiv.org",
 };
 
+// Crawler
+pub static CRAWL_TASKS: Lazy<u16> = Lazy::new(|| env_or("CRAWL_TASKS", 64)); // concurrent crawl loops
+pub static MAX_BODY_BYTES: Lazy<usize> = Lazy::new(|| env_or("MAX_BODY_BYTES", 10 * 1024 * 1024)); // larger bodies are truncated
+pub static FIXTURE_DIR: Lazy<Option<String>> =
+    Lazy::new(|| std::env::var("FIXTURE_DIR").ok().filter(|dir| !dir.is_empty())); // serve pages from recorded responses (offline)
+
+// Quickwit
+pub use crate::common::quickwit::{QUICKWIT_INDEX, QUICKWIT_URL};
+
+// Reindex
+pub static REINDEX_RATE: Lazy<u32> = Lazy::new(|| env_or("REINDEX_RATE", 200)); // documents per second
+pub const REINDEX_BATCH: usize = 100; // documents per ingest request (and per checkpoint)
+
+// WARC archive
+pub static WARC_DIR: Lazy<Option<String>> =
+    Lazy::new(|| std::env::var("WARC_DIR").ok().filter(|dir| !dir.is_empty())); // raw responses are archived here when set
+pub static WARC_MAX_FILE_BYTES: Lazy<u64> = Lazy::new(|| env_or("WARC_MAX_FILE_BYTES", 1024 * 1024 * 1024)); // rotate to a new file after this
+
+// Crawler identity
+pub static CRAWLER_TOKEN: Lazy<String> = Lazy::new(|| env_or("CRAWLER_TOKEN", "ReSearchBot".to_string())); // product token, matched against robots.txt user-agent groups
+pub static CRAWLER_CONTACT_URL: Lazy<String> =
+    Lazy::new(|| env_or("CRAWLER_CONTACT_URL", "https://github.com/ArunDtej/Re-Search".to_string())); // where site operators learn about us
+pub static CRAWLER_UA: Lazy<String> = Lazy::new(|| {
+    let ua = format!("{}/{} (+{})", *CRAWLER_TOKEN, env!("CARGO_PKG_VERSION"), *CRAWLER_CONTACT_URL);
+    if ua.chars().all(|c| c.is_ascii_graphic() || c == ' ') {
+        ua
+    } else {
+        format!("ReSearchBot/{}", env!("CARGO_PKG_VERSION")) // not a valid header value
+    }
+});
+pub static BROWSER_UA_DOMAINS: Lazy<Vec<String>> = Lazy::new(|| {
+    // comma separated; these domains (and their subdomains) get a rotated browser UA instead
+    env_or("BROWSER_UA_DOMAINS", String::new())
+        .split(',')
+        .map(|d| d.trim().trim_start_matches("www.").to_lowercase())
+        .filter(|d| !d.is_empty())
+        .collect()
+});
+
+// Near-duplicates (SimHash)
+pub const SIMHASH_MIN_WORDS: usize = 50; // shorter texts are not fingerprinted
+pub const NEAR_DUP_MAX_DISTANCE: u32 = 6; // differing SimHash bits for two texts to count as near-duplicates (at most 6, see neardup)
+pub static NEAR_DUP_SKIP: Lazy<bool> = Lazy::new(|| env_or("NEAR_DUP_SKIP", false)); // don't ingest near-duplicates at all (default: ingest, linked by cluster_id)
+
+// Per-domain budgets (0 = unlimited)
+pub static BUDGET_MAX_PAGES: Lazy<u64> = Lazy::new(|| env_or("BUDGET_MAX_PAGES", 500_000)); // pages fetched per domain, ever
+pub static BUDGET_MAX_PAGES_PER_DAY: Lazy<u64> = Lazy::new(|| env_or("BUDGET_MAX_PAGES_PER_DAY", 20_000)); // pages fetched per domain per UTC day
+pub static BUDGET_MAX_DEPTH: Lazy<u32> = Lazy::new(|| env_or("BUDGET_MAX_DEPTH", 12)); // link hops from a seed / sitemap / feed url
+pub static BUDGET_MAX_BYTES: Lazy<u64> = Lazy::new(|| env_or("BUDGET_MAX_BYTES", 100 * 1024 * 1024 * 1024)); // bodies downloaded per domain
+pub static DOMAIN_BUDGETS: Lazy<String> = Lazy::new(|| env_or("DOMAIN_BUDGETS", String::new())); // overrides: "github.com:pages=20000,day=2000;nasa.gov:depth=4"
+pub const BUDGET_RECHECK_SECS: u64 = 60 * 60 * 6; // urls over a total / bytes / depth budget are looked at again after this
+
+// Soft-404s
+pub const SOFT404_PROBE_TTL_SECS: u64 = 60 * 60 * 24 * 7; // re-learn a host's error page weekly
+pub const SOFT404_MAX_TEXT: usize = 2000; // longer pages are never judged by title / wording
+
+// robots.txt
+pub const ROBOTS_TTL_SECS: u64 = 60 * 60 * 24; // cache parsed robots.txt for a day
+pub const ROBOTS_UNREACHABLE_TTL_SECS: u64 = 60 * 60; // retry unreachable robots.txt after an hour
+pub const ROBOTS_MAX_BYTES: usize = 500 * 1024; // RFC 9309 minimum parse limit
+
+// Sitemaps
+pub const SITEMAP_TTL_SECS: u64 = 60 * 60 * 24 * 7; // re-read a site's sitemaps weekly
+pub const SITEMAP_MAX_FILES: usize = 50; // sitemap files fetched per origin and run
+pub const SITEMAP_MAX_URLS: usize = 50_000; // page urls taken per origin and run
+pub const SITEMAP_MAX_BYTES: usize = 50 * 1024 * 1024; // protocol limit (uncompressed)
+pub const SITEMAP_HIGH_PRIORITY: f32 = 0.8; // <priority> at or above this jumps the queue
+
+// Feeds (RSS / Atom)
+pub const FEED_MIN_INTERVAL_SECS: u64 = 15 * 60; // never poll a feed more often
+pub const FEED_MAX_INTERVAL_SECS: u64 = 60 * 60 * 24; // poll even quiet feeds daily
+pub const FEED_DEFAULT_INTERVAL_SECS: u64 = 60 * 60; // feeds without item dates
+pub const FEED_LEASE_SECS: u64 = 10 * 60; // a claimed feed is due again after this if its poller dies
+pub const FEED_BATCH: usize = 10; // feeds claimed per round
+pub const FEED_MAX_PARSE_FAILURES: u64 = 5; // consecutive unparseable polls before a feed is dropped
+
+// Politeness (per host)
+pub static HOST_DELAY_MS: Lazy<u64> = Lazy::new(|| env_or("HOST_DELAY_MS", 1000)); // min gap between requests to one host
+pub const MAX_HOST_DELAY_MS: u64 = 60 * 1000; // cap for robots.txt Crawl-delay
+pub const DEFAULT_RETRY_AFTER_SECS: u64 = 60; // 429 without a Retry-After header
+pub const MAX_RETRY_AFTER_SECS: u64 = 60 * 60; // never park a host longer than this
+
+// Retries
+pub static FETCH_RETRIES: Lazy<u32> = Lazy::new(|| env_or("FETCH_RETRIES", 2)); // in-process retries for transient errors
+pub const RETRY_BASE_MS: u64 = 500; // first in-process retry after ~0.5-1s
+pub const REQUEUE_BASE_SECS: u64 = 60; // first requeue after ~2-3 min
+pub const MAX_FETCH_ATTEMPTS: u32 = 5; // requeues before a url is dropped
+
+// Recrawl
+pub const VALIDATORS_TTL_SECS: u64 = 60 * 60 * 24 * 90; // forget ETag / Last-Modified after 90 days
+
 
 pub const UA: [&str; 28] = [
     "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
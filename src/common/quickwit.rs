@@ -0,0 +1,3 @@
+// Quickwit endpoint, shared by the crawler and the API binary so both use the same index
+pub const QUICKWIT_URL: &str = "http://127.0.0.1:7280";
+pub const QUICKWIT_INDEX: &str = "pages"; // index the crawler ingests into and the API searches
@@ -1,8 +1,34 @@
 use crate::common::UA;
+use crate::common::config::{BROWSER_UA_DOMAINS, CRAWLER_UA};
 use rand::prelude::*;
+use std::env;
+use std::str::FromStr;
+use url::Url;
 
 
 pub fn random_ua() -> &'static str {
     let mut rng = rand::thread_rng();
     UA.choose(&mut rng).unwrap()
-}
\ No newline at end of file
+}
+
+/// User-Agent for a request to `url`: the crawler identity, or a rotated browser string
+/// for domains listed in BROWSER_UA_DOMAINS
+pub fn user_agent(url: &str) -> &'static str {
+    let host = Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h.trim_start_matches("www.").to_lowercase()))
+        .unwrap_or_default();
+    let browser_override = BROWSER_UA_DOMAINS
+        .iter()
+        .any(|domain| host == *domain || host.ends_with(&format!(".{}", domain)));
+
+    if browser_override { random_ua() } else { CRAWLER_UA.as_str() }
+}
+
+/// Reads a setting from the environment (.env), falling back to `default`
+pub fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(default)
+}
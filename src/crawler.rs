@@ -1,5 +1,26 @@
 pub mod crawl;
 pub mod utils;
 pub mod core;
+pub mod robots;
+pub mod politeness;
+pub mod fetch;
+pub mod fetcher;
+pub mod charset;
+pub mod retry;
+pub mod pdf;
+pub mod sitemap;
+pub mod feeds;
+pub mod warc;
+pub mod reindex;
+pub mod import;
+pub mod protection;
+pub mod directives;
+pub mod canonical;
+pub mod neardup;
+pub mod soft404;
+pub mod budget;
+pub mod readability;
+pub mod scholarly;
+pub mod structured;
 
 pub use utils::clean_url;
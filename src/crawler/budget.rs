@@ -0,0 +1,313 @@
+use chrono::{Duration as ChronoDuration, Utc};
+use once_cell::sync::Lazy;
+use r2d2_redis::redis::{RedisResult, cmd, pipe};
+use std::collections::HashMap;
+use url::Url;
+
+use crate::common::DOMAINS_SET;
+use crate::common::config::{
+    BUDGET_MAX_BYTES, BUDGET_MAX_DEPTH, BUDGET_MAX_PAGES, BUDGET_MAX_PAGES_PER_DAY, BUDGET_RECHECK_SECS,
+    DOMAIN_BUDGETS,
+};
+use crate::db::{get_kv_conn, paths};
+
+/// Depth keys outlive the crawl of a url only as long as `crawl_seen` does
+const DEPTH_TTL_SECS: u64 = 60 * 60 * 24 * 31;
+
+/// What one domain may use. 0 = unlimited.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Budget {
+    pub max_pages: u64,
+    pub max_pages_per_day: u64,
+    pub max_depth: u32, // link hops from a seed, sitemap or feed
+    pub max_bytes: u64,
+}
+
+impl Budget {
+    fn defaults() -> Self {
+        Budget {
+            max_pages: *BUDGET_MAX_PAGES,
+            max_pages_per_day: *BUDGET_MAX_PAGES_PER_DAY,
+            max_depth: *BUDGET_MAX_DEPTH,
+            max_bytes: *BUDGET_MAX_BYTES,
+        }
+    }
+
+    fn too_deep(&self, depth: u32) -> bool {
+        self.max_depth > 0 && depth > self.max_depth
+    }
+
+    /// Applies `pages=..,day=..,depth=..,bytes=..` on top of `self`
+    fn with_overrides(mut self, spec: &str) -> Self {
+        for setting in spec.split(',') {
+            let Some((name, value)) = setting.split_once('=') else { continue };
+            let value = value.trim();
+            match name.trim() {
+                "pages" => self.max_pages = value.parse().unwrap_or(self.max_pages),
+                "day" => self.max_pages_per_day = value.parse().unwrap_or(self.max_pages_per_day),
+                "depth" => self.max_depth = value.parse().unwrap_or(self.max_depth),
+                "bytes" => self.max_bytes = value.parse().unwrap_or(self.max_bytes),
+                other => eprintln!("⚠️ Unknown budget setting {:?} in DOMAIN_BUDGETS", other),
+            }
+        }
+        self
+    }
+}
+
+/// Per-domain budgets from DOMAIN_BUDGETS (`github.com:pages=20000,day=2000;nasa.gov:depth=4`)
+static BUDGETS: Lazy<HashMap<String, Budget>> = Lazy::new(|| {
+    DOMAIN_BUDGETS
+        .split(';')
+        .filter_map(|entry| entry.split_once(':'))
+        .map(|(domain, spec)| {
+            let domain = domain.trim().trim_start_matches("www.").to_lowercase();
+            (domain, Budget::defaults().with_overrides(spec))
+        })
+        .collect()
+});
+
+pub fn budget_for(domain: &str) -> Budget {
+    BUDGETS.get(domain).copied().unwrap_or_else(Budget::defaults)
+}
+
+/// The domain a url is budgeted under: its DOMAINS_SET entry (subdomains share it), else its host
+pub fn domain_of(url: &str) -> Option<String> {
+    let host = Url::parse(url).ok()?.host_str()?.to_lowercase();
+    let host = host.trim_start_matches("www.");
+    let configured = DOMAINS_SET
+        .iter()
+        .copied()
+        .chain(BUDGETS.keys().map(String::as_str))
+        .find(|domain| host == *domain || host.ends_with(&format!(".{}", domain)))
+        .map(|domain| domain.to_string());
+    Some(configured.unwrap_or_else(|| host.to_string()))
+}
+
+/// A domain's spending so far
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Usage {
+    pub pages: u64,
+    pub pages_today: u64,
+    pub bytes: u64,
+}
+
+impl Usage {
+    pub fn load(domain: &str) -> Self {
+        let mut kv_conn = get_kv_conn();
+        let result: RedisResult<(Option<u64>, Option<u64>, Option<u64>)> = pipe()
+            .cmd("HGET")
+            .arg(usage_key(domain))
+            .arg("pages")
+            .cmd("HGET")
+            .arg(usage_key(domain))
+            .arg("bytes")
+            .cmd("GET")
+            .arg(daily_key(domain))
+            .query(&mut *kv_conn);
+        let (pages, bytes, pages_today) = result.unwrap_or_default();
+        Usage {
+            pages: pages.unwrap_or(0),
+            pages_today: pages_today.unwrap_or(0),
+            bytes: bytes.unwrap_or(0),
+        }
+    }
+}
+
+fn usage_key(domain: &str) -> String {
+    format!("{}:{}", paths::BUDGET_USAGE, domain)
+}
+
+fn daily_key(domain: &str) -> String {
+    format!("{}:{}:{}", paths::BUDGET_DAILY, domain, Utc::now().format("%Y-%m-%d"))
+}
+
+fn depth_key(url: &str) -> String {
+    format!("{}:{}", paths::DEPTH, url)
+}
+
+/// Why a url may not be crawled now, and how long to defer it (ms)
+pub fn over_budget(budget: &Budget, usage: &Usage, depth: u32) -> Option<(&'static str, u64)> {
+    let recheck_ms = BUDGET_RECHECK_SECS * 1000;
+    if budget.too_deep(depth) {
+        return Some(("depth", recheck_ms));
+    }
+    if budget.max_pages > 0 && usage.pages >= budget.max_pages {
+        return Some(("pages", recheck_ms));
+    }
+    if budget.max_bytes > 0 && usage.bytes >= budget.max_bytes {
+        return Some(("bytes", recheck_ms));
+    }
+    if budget.max_pages_per_day > 0 && usage.pages_today >= budget.max_pages_per_day {
+        // The daily quota resets at midnight UTC
+        let tomorrow = (Utc::now() + ChronoDuration::days(1)).date_naive().and_hms_opt(0, 0, 0)?;
+        let wait_ms = (tomorrow.and_utc().timestamp_millis() - Utc::now().timestamp_millis()).max(0) as u64;
+        return Some(("day", wait_ms));
+    }
+    None
+}
+
+/// Budget check for a url about to be fetched
+pub fn check(url: &str) -> Option<(&'static str, u64)> {
+    let domain = domain_of(url)?;
+    over_budget(&budget_for(&domain), &Usage::load(&domain), depth_of(url))
+}
+
+/// Whether a url found at link depth `depth` is beyond its domain's depth limit
+pub fn too_deep(url: &str, depth: u32) -> bool {
+    domain_of(url).is_some_and(|domain| budget_for(&domain).too_deep(depth))
+}
+
+/// Budget checks for a batch of urls about to be queued; usage is read once per domain
+#[derive(Default)]
+pub struct Checker {
+    usage: HashMap<String, Usage>,
+}
+
+impl Checker {
+    pub fn check(&mut self, url: &str, depth: u32) -> Option<(&'static str, u64)> {
+        let domain = domain_of(url)?;
+        let usage = *self.usage.entry(domain.clone()).or_insert_with(|| Usage::load(&domain));
+        over_budget(&budget_for(&domain), &usage, depth)
+    }
+}
+
+/// Link depth of a queued url (0 for seeds, sitemap and feed urls)
+pub fn depth_of(url: &str) -> u32 {
+    let mut kv_conn = get_kv_conn();
+    cmd("GET").arg(depth_key(url)).query(&mut *kv_conn).unwrap_or(None).unwrap_or(0)
+}
+
+/// Remembers the link depth of newly queued urls
+pub fn set_depths(urls: &[&str], depth: u32) {
+    if depth == 0 || urls.is_empty() {
+        return;
+    }
+    let mut pipeline = pipe();
+    for url in urls {
+        pipeline.cmd("SET").arg(depth_key(url)).arg(depth).arg("EX").arg(DEPTH_TTL_SECS).ignore();
+    }
+    let mut kv_conn = get_kv_conn();
+    let _: RedisResult<()> = pipeline.query(&mut *kv_conn);
+}
+
+/// Charges a completed fetch (200 or 304) to its domain's page counts (total and today)
+pub fn charge_page(url: &str) {
+    let Some(domain) = domain_of(url) else { return };
+    let daily = daily_key(&domain);
+
+    let mut pipeline = pipe();
+    pipeline.cmd("HINCRBY").arg(usage_key(&domain)).arg("pages").arg(1).ignore();
+    pipeline.cmd("INCR").arg(&daily).ignore();
+    pipeline.cmd("EXPIRE").arg(&daily).arg(60 * 60 * 48).ignore();
+
+    let mut kv_conn = get_kv_conn();
+    let _: RedisResult<()> = pipeline.query(&mut *kv_conn);
+}
+
+/// Charges a downloaded body to its domain's byte count
+pub fn charge_bytes(url: &str, bytes: u64) {
+    let Some(domain) = domain_of(url) else { return };
+    let mut kv_conn = get_kv_conn();
+    let _: RedisResult<()> = cmd("HINCRBY").arg(usage_key(&domain)).arg("bytes").arg(bytes).query(&mut *kv_conn);
+}
+
+/// Counts a url deferred for being over budget (`bg:<domain>` field `deferred_<reason>`)
+pub fn record_deferred(url: &str, reason: &str) {
+    let Some(domain) = domain_of(url) else { return };
+    let mut kv_conn = get_kv_conn();
+    let _: RedisResult<()> = cmd("HINCRBY")
+        .arg(usage_key(&domain))
+        .arg(format!("deferred_{}", reason))
+        .arg(1)
+        .query(&mut *kv_conn);
+}
+
+/// `research budgets`: usage against budget for every seed domain and every configured one
+pub fn report() {
+    let mut domains: Vec<String> = DOMAINS_SET.iter().map(|d| d.to_string()).collect();
+    domains.extend(BUDGETS.keys().filter(|d| !DOMAINS_SET.contains(d.as_str())).cloned());
+    domains.sort();
+
+    let limit = |max: u64| if max == 0 { "∞".to_string() } else { max.to_string() };
+    println!(
+        "{:<24} {:>18} {:>16} {:>26} {:>6} {:>9}",
+        "domain", "pages", "today", "bytes", "depth", "deferred"
+    );
+    let mut kv_conn = get_kv_conn();
+    for domain in domains {
+        let budget = budget_for(&domain);
+        let usage = Usage::load(&domain);
+        let counts: HashMap<String, u64> = cmd("HGETALL").arg(usage_key(&domain)).query(&mut *kv_conn).unwrap_or_default();
+        let deferred: u64 = counts
+            .iter()
+            .filter(|(field, _)| field.starts_with("deferred_"))
+            .map(|(_, count)| count)
+            .sum();
+
+        println!(
+            "{:<24} {:>18} {:>16} {:>26} {:>6} {:>9}",
+            domain,
+            format!("{}/{}", usage.pages, limit(budget.max_pages)),
+            format!("{}/{}", usage.pages_today, limit(budget.max_pages_per_day)),
+            format!("{}/{}", usage.bytes, limit(budget.max_bytes)),
+            limit(budget.max_depth as u64),
+            deferred
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUDGET: Budget = Budget {
+        max_pages: 100,
+        max_pages_per_day: 10,
+        max_depth: 3,
+        max_bytes: 1000,
+    };
+
+    #[test]
+    fn overrides_apply_on_top_of_the_budget() {
+        let budget = BUDGET.with_overrides("pages=20000, day=2000,depth=4,bytes=oops,unknown=1,novalue");
+        assert_eq!(
+            budget,
+            Budget {
+                max_pages: 20000,
+                max_pages_per_day: 2000,
+                max_depth: 4,
+                max_bytes: 1000, // unparseable values keep the previous limit
+            }
+        );
+        assert_eq!(BUDGET.with_overrides(""), BUDGET);
+    }
+
+    #[test]
+    fn depth_and_totals_are_deferred() {
+        let usage = Usage::default();
+        let recheck_ms = BUDGET_RECHECK_SECS * 1000;
+        assert_eq!(over_budget(&BUDGET, &usage, 3), None);
+        assert_eq!(over_budget(&BUDGET, &usage, 4), Some(("depth", recheck_ms)));
+        assert!(!BUDGET.too_deep(3) && BUDGET.too_deep(4));
+
+        let spent = Usage { pages: 100, ..usage };
+        assert_eq!(over_budget(&BUDGET, &spent, 0), Some(("pages", recheck_ms)));
+        let spent = Usage { bytes: 1000, ..usage };
+        assert_eq!(over_budget(&BUDGET, &spent, 0), Some(("bytes", recheck_ms)));
+    }
+
+    #[test]
+    fn daily_quota_waits_until_midnight() {
+        let usage = Usage { pages_today: 10, ..Usage::default() };
+        let (reason, wait_ms) = over_budget(&BUDGET, &usage, 0).unwrap();
+        assert_eq!(reason, "day");
+        assert!(wait_ms <= 24 * 60 * 60 * 1000);
+    }
+
+    #[test]
+    fn zero_means_unlimited() {
+        let unlimited = Budget { max_pages: 0, max_pages_per_day: 0, max_depth: 0, max_bytes: 0 };
+        let usage = Usage { pages: u64::MAX, pages_today: u64::MAX, bytes: u64::MAX };
+        assert_eq!(over_budget(&unlimited, &usage, u32::MAX), None);
+    }
+}
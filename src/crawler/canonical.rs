@@ -0,0 +1,137 @@
+use r2d2_redis::redis::{RedisResult, cmd, pipe};
+use reqwest::StatusCode;
+use reqwest::header::USER_AGENT;
+use std::time::Duration;
+use url::Url;
+
+use crate::common::config::CRAWLER_TOKEN;
+use crate::common::utils::user_agent;
+use crate::crawler::clean_url;
+use crate::crawler::crawl::PageMetadata;
+use crate::crawler::fetch::HTTP_CLIENT;
+use crate::crawler::{politeness, robots, utils};
+use crate::db::{get_kv_conn, paths};
+
+/// Moves a page to its `<link rel="canonical">` identity when the canonical is same-site and reachable.
+///
+/// The fetched url is kept in `alternate_urls`, remembered as an alias of the canonical
+/// (future backlinks count for the canonical) and its backlink score is folded in.
+/// Whether this crawl may index the canonical is up to `utils::claim_document`.
+pub async fn consolidate(metadata: &mut PageMetadata) {
+    let Some(canonical) = candidate(metadata) else { return };
+    if !reachable(&canonical).await {
+        return;
+    }
+    let variant = adopt(metadata, canonical);
+    record_alias(&variant, &metadata.url);
+}
+
+/// `consolidate` for archived pages, without requests or writes: only a canonical that was
+/// crawled or archived is adopted. Returns the replaced url.
+pub fn consolidate_known(metadata: &mut PageMetadata) -> Option<String> {
+    let canonical = candidate(metadata)?;
+    is_known(&canonical).then(|| adopt(metadata, canonical))
+}
+
+/// The page's canonical, when it names a different, same-site document
+fn candidate(metadata: &PageMetadata) -> Option<String> {
+    let canonical = metadata.canonical_url.as_deref().and_then(clean_url)?;
+    // A canonical we were redirected away from is not a separate, reachable document
+    let redirected_from = metadata
+        .redirects
+        .iter()
+        .any(|hop| clean_url(&hop.url).as_deref() == Some(canonical.as_str()));
+    (canonical != metadata.url && !redirected_from && same_site(&metadata.url, &canonical)).then_some(canonical)
+}
+
+fn adopt(metadata: &mut PageMetadata, canonical: String) -> String {
+    let variant = std::mem::replace(&mut metadata.url, canonical);
+    metadata.alternate_urls.push(variant.clone());
+    variant
+}
+
+/// Points `variant` at `canonical` in the live crawl state: alias, backlink score, `crawl_seen`
+pub fn record_alias(variant: &str, canonical: &str) {
+    let mut kv_conn = get_kv_conn();
+    let variant_score: Option<i64> = cmd("GET")
+        .arg(format!("{}:{}", paths::URL_SCORE, variant))
+        .query(&mut *kv_conn)
+        .unwrap_or(None);
+
+    let mut pipeline = pipe();
+    pipeline.cmd("HSET").arg(paths::CANONICAL_ALIAS).arg(variant).arg(canonical).ignore();
+    if let Some(score) = variant_score.filter(|s| *s > 0) {
+        pipeline.cmd("INCRBY").arg(format!("{}:{}", paths::URL_SCORE, canonical)).arg(score).ignore();
+        pipeline.cmd("DEL").arg(format!("{}:{}", paths::URL_SCORE, variant)).ignore();
+    }
+    // Reached through its variants, the canonical itself need not be queued
+    pipeline.cmd("BF.ADD").arg(paths::CRAWL_SEEN).arg(utils::url_hash(canonical)).ignore();
+
+    if let Err(err) = pipeline.query::<()>(&mut *kv_conn) {
+        eprintln!("⚠️ Canonical consolidation failed for {}: {}", variant, err);
+    }
+    println!("🔗 Canonical {} -> {}", variant, canonical);
+}
+
+/// Same host, ignoring `www.` and subdomains of one another (e.g. `link.springer.com` / `springer.com`)
+fn same_site(a: &str, b: &str) -> bool {
+    let host = |url: &str| {
+        Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.trim_start_matches("www.").to_lowercase()))
+    };
+    let (Some(a), Some(b)) = (host(a), host(b)) else { return false };
+    a == b || a.ends_with(&format!(".{}", b)) || b.ends_with(&format!(".{}", a))
+}
+
+/// Crawled before, or captured in the WARC archive
+fn is_known(canonical: &str) -> bool {
+    if utils::last_crawled_many(&[canonical.to_string()])[0].is_some() {
+        return true;
+    }
+    let mut kv_conn = get_kv_conn();
+    cmd("EXISTS")
+        .arg(format!("{}:{}", paths::WARC_INDEX, canonical))
+        .query(&mut *kv_conn)
+        .unwrap_or(false)
+}
+
+/// Crawled before, or allowed by robots.txt and answering a HEAD with 2xx (405: no HEAD support)
+async fn reachable(canonical: &str) -> bool {
+    if utils::last_crawled_many(&[canonical.to_string()])[0].is_some() {
+        return true;
+    }
+
+    let Ok(parsed) = Url::parse(canonical) else { return false };
+    let rules = robots::rules_for(&parsed).await;
+    if rules.disallow_reason(&CRAWLER_TOKEN, &parsed).is_some() {
+        return false;
+    }
+    politeness::wait_for_slot(parsed.host_str().unwrap_or_default(), politeness::host_delay_ms(&rules)).await;
+
+    let response = HTTP_CLIENT
+        .head(canonical)
+        .header(USER_AGENT, user_agent(canonical))
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await;
+    matches!(response, Ok(resp) if resp.status().is_success() || resp.status() == StatusCode::METHOD_NOT_ALLOWED)
+}
+
+/// Canonical urls for a batch of urls (the url itself when it has no known canonical)
+pub fn resolve_many(urls: &[String]) -> Vec<String> {
+    if urls.is_empty() {
+        return Vec::new();
+    }
+
+    let mut kv_conn = get_kv_conn();
+    let aliases: RedisResult<Vec<Option<String>>> = cmd("HMGET").arg(paths::CANONICAL_ALIAS).arg(urls).query(&mut *kv_conn);
+    match aliases {
+        Ok(aliases) => urls
+            .iter()
+            .zip(aliases)
+            .map(|(url, alias)| alias.unwrap_or_else(|| url.clone()))
+            .collect(),
+        Err(_) => urls.to_vec(),
+    }
+}
@@ -0,0 +1,134 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE};
+use url::Url;
+
+/// How many leading bytes are scanned for `<meta charset>` (HTML spec uses 1024, be generous)
+const META_PRESCAN_BYTES: usize = 4096;
+
+/// Decodes an HTML body to UTF-8.
+///
+/// Encoding is chosen like a browser would: BOM, then the Content-Type `charset`,
+/// then `<meta charset>` / `http-equiv`, then a statistical guess (chardetng).
+/// Returns the text and the name of the encoding actually used.
+pub fn decode_html(body: &[u8], content_type: Option<&str>, url: &str) -> (String, &'static str) {
+    let encoding = Encoding::for_bom(body)
+        .map(|(enc, _)| enc)
+        .or_else(|| content_type.and_then(charset_from_content_type))
+        .or_else(|| charset_from_meta(body))
+        .unwrap_or_else(|| sniff(body, url));
+
+    // `decode` strips a BOM if present and switches to the BOM's encoding
+    let (text, used, _had_errors) = encoding.decode(body);
+    (text.into_owned(), used.name())
+}
+
+fn charset_from_content_type(content_type: &str) -> Option<&'static Encoding> {
+    let lower = content_type.to_lowercase();
+    let (_, charset) = lower.split_once("charset=")?;
+    label_to_encoding(charset)
+}
+
+fn charset_from_meta(body: &[u8]) -> Option<&'static Encoding> {
+    let prefix = &body[..body.len().min(META_PRESCAN_BYTES)];
+    let head = String::from_utf8_lossy(prefix).to_lowercase();
+
+    let mut rest = head.as_str();
+    while let Some(start) = rest.find("<meta") {
+        let tag_and_after = &rest[start..];
+        let end = tag_and_after.find('>').unwrap_or(tag_and_after.len());
+        let tag = &tag_and_after[..end];
+
+        // covers both <meta charset="x"> and <meta http-equiv content="text/html; charset=x">
+        if let Some((_, value)) = tag.split_once("charset")
+            && let Some(value) = value.trim_start().strip_prefix('=')
+            && let Some(encoding) = label_to_encoding(value)
+        {
+            // A page can't really be UTF-16 if we could read this tag as ASCII
+            if encoding == UTF_16LE || encoding == UTF_16BE {
+                return Some(UTF_8);
+            }
+            return Some(encoding);
+        }
+
+        rest = &tag_and_after[end..];
+    }
+    None
+}
+
+fn label_to_encoding(raw: &str) -> Option<&'static Encoding> {
+    let label: String = raw
+        .trim_start()
+        .trim_start_matches(['"', '\''])
+        .chars()
+        .take_while(|c| !matches!(c, '"' | '\'' | ';' | ' ' | '/' | '>' | ','))
+        .collect();
+    Encoding::for_label(label.trim().as_bytes())
+}
+
+/// Last resort: guess from the bytes, using the url's TLD as a hint (e.g. `.jp`, `.cn`)
+fn sniff(body: &[u8], url: &str) -> &'static Encoding {
+    let host = Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h.to_string()))
+        .unwrap_or_default();
+    let tld = host.rsplit('.').next().filter(|t| !t.is_empty());
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(body, true);
+    detector.guess(tld.map(|t| t.as_bytes()), true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::SHIFT_JIS;
+
+    const URL: &str = "https://fixture.test/";
+
+    #[test]
+    fn content_type_charset_is_used() {
+        let body = b"<html><body>Caf\xe9</body></html>";
+        let (text, used) = decode_html(body, Some("text/html; Charset=\"ISO-8859-1\""), URL);
+        assert_eq!(text, "<html><body>Café</body></html>");
+        assert_eq!(used, "windows-1252"); // what browsers do for latin1
+    }
+
+    #[test]
+    fn meta_charset_and_bom_are_used() {
+        let (body, _, _) = SHIFT_JIS.encode("<meta charset=\"shift_jis\"><p>日本語のページ</p>");
+        let (text, used) = decode_html(&body, Some("text/html"), URL);
+        assert_eq!(used, "Shift_JIS");
+        assert!(text.ends_with("<p>日本語のページ</p>"));
+
+        let body = b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=windows-1251\">\xcf\xf0\xe8\xe2\xe5\xf2";
+        let (text, used) = decode_html(body, None, URL);
+        assert_eq!(used, "windows-1251");
+        assert!(text.ends_with(">Привет"));
+
+        // Readable as ASCII, so not really UTF-16
+        assert_eq!(decode_html(b"<meta charset=utf-16>ok", None, URL).1, "UTF-8");
+
+        // The BOM wins over everything, and is stripped
+        let (text, used) = decode_html(b"\xef\xbb\xbf<p>Caf\xc3\xa9</p>", Some("text/html; charset=iso-8859-1"), URL);
+        assert_eq!((text.as_str(), used), ("<p>Café</p>", "UTF-8"));
+    }
+
+    #[test]
+    fn unlabelled_pages_are_sniffed() {
+        let text = "日本語のウェブページです。クローラーはこのページを正しく読み込む必要があります。".repeat(4);
+        let (body, _, _) = SHIFT_JIS.encode(&text);
+        let (decoded, used) = decode_html(&body, Some("text/html"), "https://fixture.jp/");
+        assert_eq!(used, "Shift_JIS");
+        assert_eq!(decoded, text);
+    }
+
+    #[test]
+    fn header_wins_over_a_conflicting_meta() {
+        let body = "<meta charset=\"windows-1252\"><p>Café</p>".as_bytes();
+        let (text, used) = decode_html(body, Some("text/html; charset=utf-8"), URL);
+        assert_eq!((text.as_str(), used), ("<meta charset=\"windows-1252\"><p>Café</p>", "UTF-8"));
+
+        let (text, used) = decode_html(body, None, URL);
+        assert_eq!((text.as_str(), used), ("<meta charset=\"windows-1252\"><p>CafÃ©</p>", "windows-1252"));
+    }
+}
@@ -1,5 +1,20 @@
 use crate::common::DOMAINS_SET;
-use crate::crawler::crawl::crawl_page;
+use crate::common::config::{
+    CRAWL_TASKS, FETCH_RETRIES, QUICKWIT_INDEX, QUICKWIT_URL, RETRY_BASE_MS, CRAWLER_TOKEN, NEAR_DUP_SKIP,
+};
+use crate::crawler::budget;
+use crate::crawler::canonical;
+use crate::crawler::clean_url;
+use crate::crawler::crawl::{CrawlOutcome, CrawlResult, PageMetadata, crawl_page};
+use crate::crawler::fetch::FetchError;
+use crate::crawler::politeness::{self, Slot};
+use crate::crawler::protection;
+use crate::crawler::retry;
+use crate::crawler::robots;
+use crate::crawler::feeds;
+use crate::crawler::neardup;
+use crate::crawler::sitemap;
+use crate::crawler::soft404;
 use crate::crawler::utils;
 use crate::db::get_kv_conn;
 use crate::db::paths;
@@ -16,12 +31,13 @@ use std::time::Duration;
 use tokio::task;
 use tokio::time::sleep;
 use serde_json::json;
+use url::Url;
 
 pub async fn traverse() {
     let mut conn = get_kv_conn();
     ensure_bloom_filter(&mut conn); // optional (auto creates filter if missing)
 
-    let num_tasks: u16 = 8; // lightweight async tasks
+    let num_tasks = *CRAWL_TASKS; // lightweight async tasks, fetches don't hold a thread
 
     for i in 0..num_tasks {
         task::spawn(async move {
@@ -30,6 +46,9 @@ pub async fn traverse() {
         });
     }
 
+    // Feed poller: new items from RSS / Atom feeds jump the queue
+    task::spawn(feeds::poll_loop());
+
     // keep main alive
     loop {
         sleep(Duration::from_secs(3600)).await;
@@ -41,76 +60,313 @@ async fn crawler_thread() {
     let mut conn: r2d2::PooledConnection<r2d2_redis::RedisConnectionManager> = get_kv_conn();
 
     loop {
-        let url: Option<String> = cmd("RPOP")
-            .arg(crawl_list)
-            .query(&mut *conn)
-            .unwrap_or(None);
+        // Urls whose host cooldown has passed go first, then the main crawl list
+        let url: Option<String> = politeness::pop_ready().or_else(|| {
+            cmd("RPOP")
+                .arg(crawl_list)
+                .query(&mut *conn)
+                .unwrap_or(None)
+        });
 
         match url {
             Some(url) => {
                 println!("Fetched url {}", url);
-                let res = index_url(&url).await;
-                // println!("{:?}", res);
 
-                sleep(Duration::from_secs(1)).await;
-            }
-            None => {
-                refill_if_empty(&mut conn).await;
-                sleep(Duration::from_secs(10)).await;
+                let Ok(parsed) = Url::parse(&url) else {
+                    utils::record_skip(&url, "unparseable url");
+                    continue;
+                };
+
+                let rules = robots::rules_for(&parsed).await;
+                if let Some(reason) = rules.disallow_reason(&CRAWLER_TOKEN, &parsed) {
+                    println!("🤖 Skipped (robots) {} -> {}", url, reason);
+                    utils::record_skip(&url, &reason);
+                    continue;
+                }
+
+                sitemap::discover_once(&parsed, &rules);
+
+                // Domain out of budget -> park the url until the budget allows it again
+                if let Some((reason, wait_ms)) = budget::check(&url) {
+                    println!("💰 Over budget ({}) {}", reason, url);
+                    budget::record_deferred(&url, reason);
+                    politeness::defer(&url, wait_ms);
+                    continue;
+                }
+
+                // Host still cooling down -> park the url and move on to another host
+                let host = parsed.host_str().unwrap_or_default();
+                if let Slot::Wait(wait_ms) = politeness::try_acquire(host, politeness::host_delay_ms(&rules)) {
+                    politeness::defer(&url, wait_ms);
+                    continue;
+                }
+
+                if let Err(err) = index_url(&url).await {
+                    println!("⚠️ Failed {}: {}", url, err);
+                }
             }
+            None => match politeness::next_ready_in() {
+                // Only deferred urls left -> wait for the nearest host instead of refilling
+                Some(wait_ms) => sleep(Duration::from_millis(wait_ms.clamp(50, 1000))).await,
+                None => {
+                    refill_if_empty(&mut conn).await;
+                    sleep(Duration::from_secs(10)).await;
+                }
+            },
         }
     }
 }
 
 async fn index_url(url: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let url_owned = url.to_string();
-    let data: Option<super::crawl::CrawlResult> = tokio::task::spawn_blocking(move || crawl_page(&url_owned)).await??;
+    let page_url = clean_url(url).unwrap_or_else(|| url.to_string());
+    let validators = utils::load_validators(&page_url);
+
+    // Transient failures get a couple of quick retries, each in its own host slot;
+    // after that the url goes back to the frontier
+    let mut attempt: u32 = 0;
+    let data: CrawlOutcome = loop {
+        match crawl_page(url, &validators).await {
+            Ok(outcome) => break outcome,
+            Err(err)
+                if err.is_retryable()
+                    && !matches!(err, FetchError::RateLimited { .. })
+                    && attempt < *FETCH_RETRIES =>
+            {
+                let wait = retry::backoff(Duration::from_millis(RETRY_BASE_MS), attempt);
+                attempt += 1;
+                println!("↻ Retry {} #{} in {}ms ({})", url, attempt, wait.as_millis(), err);
+                sleep(wait).await;
+                if let Ok(parsed) = Url::parse(url) {
+                    let rules = robots::rules_for(&parsed).await;
+                    politeness::wait_for_slot(parsed.host_str().unwrap_or_default(), politeness::host_delay_ms(&rules))
+                        .await;
+                }
+            }
+            Err(err) => {
+                println!("⚠️ Skipped: {} ({})", url, err);
+                if let FetchError::Protected(reason) = err {
+                    protection::record(&page_url, Some(reason));
+                }
+                retry::requeue_or_give_up(url, &err);
+                return Ok(());
+            }
+        }
+    };
+    retry::clear_attempts(url);
 
     // println!("Crawled data for {}, data {:?}", url, data);
     let mut conn: r2d2::PooledConnection<r2d2_redis::RedisConnectionManager> = get_kv_conn();
 
     match data {
-        Some(res) => {
-            let new_urls = utils::hash_links(&res.links)?;
-            let urls_owned = new_urls.clone();    
-            
-            let _ = tokio::task::spawn_blocking(move || enqueue_and_mark_seen(&new_urls, &mut conn)).await;
-
-            let docs = json!([
-                {
-                    "url": res.metadata.url ,
-                    "title": res.metadata.title,
-                    "crawl_timestamp": res.metadata.crawl_timestamp,
-                    "cleaned_text": res.metadata.cleaned_text,
-                    "meta_description": res.metadata.meta_description,
-                    "last_modified": res.metadata.last_modified,
-                    "h1": res.metadata.h1,
-                }
-            ]);
-
-            ingest_to_quickwit(&docs, "http://127.0.0.1:7280/api/v1/pages/ingest").await?;
+        CrawlOutcome::Fetched(mut res) => {
+            budget::charge_page(url);
+            budget::charge_bytes(url, res.metadata.body_bytes);
+            res.metadata.depth = budget::depth_of(url);
+
+            // Redirected: the target is the document, the hops just point at it
+            if !res.metadata.redirects.is_empty() {
+                mark_redirects_seen(url, &res.metadata, &mut conn)?;
+            }
 
-            let link_owned = url.to_string();      // Own the String
-             
-            tokio::task::spawn_blocking(move || {
-                // ✅ Use the owned values directly — don't create new temporary strings
-                utils::back_link_score(&link_owned, &urls_owned);
-            })
-            .await
-            .expect("backlink score task failed");
+            protection::record(
+                &res.metadata.url,
+                res.metadata.is_protected.then_some(res.metadata.protection_reason.as_str()),
+            );
 
+            let mut res = *res;
+            if !prepare_page(&mut res.metadata).await {
+                return Ok(());
+            }
+            // The first url to reach a document indexes it
+            if !utils::claim_document(&res.metadata.url, &page_url) {
+                println!("🔁 Duplicate: {} -> {} is indexed by another url", url, res.metadata.url);
+                return Ok(());
+            }
+            store_page(&page_url, res).await?;
         }
-        None => {
-            println!("⚠️ Skipped: {}", url);
+        CrawlOutcome::NotModified => {
+            // Same content as last time: no parsing, no re-ingestion
+            budget::charge_page(url);
+            utils::touch_crawled(&page_url);
         }
     };
     Ok(())
 }
 
-fn enqueue_and_mark_seen(
+/// Post-fetch checks of the live crawl: drops soft-404s (`false`) and moves the page to its
+/// canonical identity. Archived pages get the request-free variants (import, reindex). Challenge / login pages never get here (`parse_page`).
+pub async fn prepare_page(metadata: &mut PageMetadata) -> bool {
+    if let Some(reason) = soft404::check(metadata).await {
+        println!("🕳️ Soft-404 ({}): {}", reason, metadata.url);
+        soft404::record(&metadata.url, reason);
+        return false;
+    }
+    canonical::consolidate(metadata).await;
+    true
+}
+
+/// Everything after parsing: validators, feeds, outlinks into the frontier, Quickwit, backlink scores.
+/// Shared by live crawling and WARC import; `requested_url` is what was fetched (before redirects
+/// and canonicalisation).
+pub async fn store_page(
+    requested_url: &str,
+    mut res: CrawlResult,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = get_kv_conn();
+
+    utils::store_validators(requested_url, &res.metadata);
+    if res.metadata.truncated {
+        utils::count_truncated();
+    }
+
+    feeds::register_feeds(&res.feeds, &res.metadata.url);
+
+    // The paper itself: a landing page's citation_pdf_url jumps the queue
+    if let Some(pdf_url) = res.metadata.scholarly.pdf_url.as_deref().and_then(clean_url)
+        && !res.metadata.directives.nofollow
+    {
+        let pdf = utils::hash_links(&vec![pdf_url])?;
+        let depth = res.metadata.depth + 1;
+        let mut pdf_conn = get_kv_conn();
+        let _ = tokio::task::spawn_blocking(move || enqueue_and_mark_seen(&pdf, Priority::High, depth, &mut pdf_conn)).await;
+    }
+
+    let new_urls = utils::hash_links(&res.links)?;
+    let urls_owned = new_urls.clone();    
+    
+    let depth = res.metadata.depth + 1;
+    let _ = tokio::task::spawn_blocking(move || enqueue_and_mark_seen(&new_urls, Priority::Normal, depth, &mut conn)).await;
+
+    if res.metadata.directives.noindex {
+        println!("🚫 noindex {}, not ingesting", res.metadata.url);
+    } else if !neardup::assign_cluster(&mut res.metadata) && *NEAR_DUP_SKIP {
+        println!("👯 Skipped near-duplicate {}", res.metadata.url);
+    } else {
+        let docs = json!([index_doc(&res.metadata)]);
+        ingest_to_quickwit(&docs, &ingest_endpoint(QUICKWIT_INDEX)).await?;
+    }
+
+    let link_owned = res.metadata.url.clone();      // Own the String
+     
+    tokio::task::spawn_blocking(move || {
+        // ✅ Use the owned values directly — don't create new temporary strings
+        utils::back_link_score(&link_owned, &urls_owned)
+    })
+    .await
+    .expect("backlink score task failed");
+
+    Ok(())
+}
+
+/// The Quickwit document for a page (fields mapped in site-index.yaml)
+pub fn index_doc(metadata: &PageMetadata) -> Value {
+    json!({
+        "url": metadata.url,
+        "title": metadata.title,
+        "crawl_timestamp": metadata.crawl_timestamp,
+        "cleaned_text": metadata.cleaned_text,
+        "meta_description": metadata.meta_description,
+        "last_modified": metadata.last_modified,
+        "h1": metadata.h1,
+        "author": metadata.author,
+        "lang": metadata.lang,
+        "og_title": metadata.og_title,
+        "og_description": metadata.og_description,
+        "og_image": metadata.og_image,
+        "og_url": metadata.og_url,
+        "meta_robots": metadata.robots,
+        "server": metadata.server,
+        "content_type": metadata.content_type,
+        "truncated": metadata.truncated,
+        "encoding": metadata.encoding,
+        "is_protected": metadata.is_protected,
+        "protection_reason": metadata.protection_reason,
+        "noarchive": metadata.directives.noarchive,
+        "nosnippet": metadata.directives.nosnippet,
+        "redirected_from": metadata.redirects.iter().map(|hop| hop.url.as_str()).collect::<Vec<_>>(),
+        "alternate_urls": metadata.alternate_urls,
+        "cluster_id": metadata.cluster_id.as_deref().unwrap_or(&metadata.url),
+        "citation_title": metadata.scholarly.title,
+        "authors": metadata.scholarly.authors,
+        "doi": metadata.scholarly.doi,
+        "publication_date": metadata.scholarly.publication_date,
+        "journal": metadata.scholarly.journal,
+        "publisher": metadata.scholarly.publisher,
+        "pdf_url": metadata.scholarly.pdf_url,
+        "abstract": metadata.scholarly.abstract_text,
+        "keywords": metadata.scholarly.keywords,
+        "schema_types": distinct(metadata.entities.iter().flat_map(|e| e.types.iter())),
+        "schema_names": distinct(metadata.entities.iter().filter_map(|e| e.name.as_ref())),
+        "schema_authors": distinct(metadata.entities.iter().flat_map(|e| e.authors.iter())),
+        "schema_published": distinct(metadata.entities.iter().filter_map(|e| e.date_published.as_ref())),
+        "schema_modified": distinct(metadata.entities.iter().filter_map(|e| e.date_modified.as_ref())),
+        "schema_identifiers": distinct(metadata.entities.iter().flat_map(|e| e.identifiers.iter())),
+    })
+}
+
+/// Values of one entity field across all of a page's entities, first occurrence kept
+fn distinct<'a>(values: impl Iterator<Item = &'a String>) -> Vec<&'a str> {
+    let mut distinct: Vec<&str> = Vec::new();
+    for value in values {
+        if !distinct.contains(&value.as_str()) {
+            distinct.push(value);
+        }
+    }
+    distinct
+}
+
+pub fn ingest_endpoint(index: &str) -> String {
+    format!("{}/api/v1/{}/ingest", QUICKWIT_URL, index)
+}
+
+/// Marks the requested url, every redirect hop and the final url as seen, so they are not queued again.
+/// Who indexes the final url is decided by `utils::claim_document`, not by this filter: it already
+/// holds every url that was ever queued, including the one being recrawled.
+fn mark_redirects_seen(
+    requested_url: &str,
+    metadata: &PageMetadata,
+    conn: &mut r2d2::PooledConnection<RedisConnectionManager>,
+) -> RedisResult<()> {
+    let mut seen_pipe = pipe();
+
+    let hop_urls = std::iter::once(requested_url).chain(metadata.redirects.iter().map(|hop| hop.url.as_str()));
+    for hop_url in hop_urls {
+        let hop_url = clean_url(hop_url).unwrap_or_else(|| hop_url.to_string());
+        if hop_url == metadata.url {
+            continue; // e.g. a redirect that only dropped tracking params
+        }
+        seen_pipe.cmd("BF.ADD").arg(paths::CRAWL_SEEN).arg(utils::url_hash(&hop_url)).ignore();
+    }
+    seen_pipe.cmd("BF.ADD").arg(paths::CRAWL_SEEN).arg(utils::url_hash(&metadata.url)).ignore();
+    seen_pipe.query(&mut **conn)
+}
+
+/// Where a url lands in the crawl list
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Priority {
+    Normal, // LPUSH: crawled after everything already queued
+    High,   // RPUSH: crawled next (the list is consumed with RPOP)
+}
+
+/// Queues urls not seen before. `depth` is their link depth; urls over their domain's budget
+/// are deferred instead of queued. Urls too deep for their domain are skipped without being
+/// marked seen, so a link from a shallower page can still queue them.
+pub fn enqueue_and_mark_seen(
    new_urls: &[(String, String)], // (url, hash)
+    priority: Priority,
+    depth: u32,
     conn: &mut r2d2::PooledConnection<RedisConnectionManager>,
 ) -> RedisResult<()> {
+    let new_urls: Vec<&(String, String)> = new_urls
+        .iter()
+        .filter(|(url, _)| {
+            let too_deep = budget::too_deep(url, depth);
+            if too_deep {
+                utils::record_skip(url, "over depth budget");
+            }
+            !too_deep
+        })
+        .collect();
     if new_urls.is_empty() {
         return Ok(());
     }
@@ -119,7 +375,7 @@ fn enqueue_and_mark_seen(
     let mut bloom_pipe = pipe();
 
     // Stage 1: build pipeline for all BF.ADD
-    for (_, hash) in new_urls {
+    for (_, hash) in &new_urls {
         bloom_pipe.cmd("BF.ADD").arg(bloom_key).arg(hash);
     }
 
@@ -129,14 +385,23 @@ fn enqueue_and_mark_seen(
     // Stage 2: LPUSH only new URLs (added == 1)
     let mut push_pipe = pipe();
     let mut push_count = 0; // <-- track number of added URLs
+    let mut budgets = budget::Checker::default();
+    let mut added_urls = Vec::new();
 
     for ((url, _), added) in new_urls.iter().zip(results) {
         println!("Added url {}, {}", url, added);
         if added == 1 {
-            push_pipe.cmd("LPUSH").arg(paths::CRAWL_LIST_PATH).arg(url);
+            added_urls.push(url.as_str());
+            if let Some((reason, wait_ms)) = budgets.check(url, depth) {
+                budget::record_deferred(url, reason);
+                politeness::defer(url, wait_ms);
+                continue;
+            }
+            push_pipe.cmd(push_cmd(priority)).arg(paths::CRAWL_LIST_PATH).arg(url);
             push_count += 1;
         }
     }
+    budget::set_depths(&added_urls, depth);
 
     if push_count > 0 {
         let _: RedisResult<()> = push_pipe.query(&mut **conn);
@@ -146,7 +411,31 @@ fn enqueue_and_mark_seen(
     Ok(())
 }
 
-fn ensure_bloom_filter(conn: &mut r2d2::PooledConnection<RedisConnectionManager>) {
+/// Queues urls that are already in `crawl_seen` but need another crawl (e.g. sitemap lastmod)
+pub fn requeue_seen(
+    urls: &[String],
+    priority: Priority,
+    conn: &mut r2d2::PooledConnection<RedisConnectionManager>,
+) -> RedisResult<()> {
+    if urls.is_empty() {
+        return Ok(());
+    }
+
+    let mut push_pipe = pipe();
+    for url in urls {
+        push_pipe.cmd(push_cmd(priority)).arg(paths::CRAWL_LIST_PATH).arg(url).ignore();
+    }
+    push_pipe.query(&mut **conn)
+}
+
+fn push_cmd(priority: Priority) -> &'static str {
+    match priority {
+        Priority::Normal => "LPUSH",
+        Priority::High => "RPUSH",
+    }
+}
+
+pub fn ensure_bloom_filter(conn: &mut r2d2::PooledConnection<RedisConnectionManager>) {
     let _: RedisResult<()> = cmd("BF.RESERVE")
         .arg(paths::CRAWL_SEEN) // filter key
         .arg(0.01) // 1% false positive rate
@@ -242,7 +531,11 @@ pub async fn ingest_to_quickwit(data: &Value, endpoint: &str) -> Result<()> {
         .send()
         .await?;
 
-    println!("✅ Status: {}", resp.status());
+    let status = resp.status();
+    println!("✅ Status: {}", status);
     println!("🔹 Response: {}", resp.text().await?);
+    if !status.is_success() {
+        anyhow::bail!("Quickwit ingest failed with HTTP {}", status);
+    }
     Ok(())
 }
@@ -1,97 +1,171 @@
 use chrono::Utc;
-use curl::easy::{Easy, List};
 use scraper::{Html, Selector};
 use std::collections::{HashMap, HashSet};
 use url::Url;
 
-use crate::common::utils::random_ua;
+use crate::common::config::CRAWLER_TOKEN;
+use crate::crawler::charset;
+use crate::crawler::directives::Directives;
 use crate::crawler::clean_url;
+use crate::crawler::fetch::{FetchError, FetchResponse, RedirectHop};
+use crate::crawler::fetcher::{FETCHER, Fetcher};
+use crate::crawler::pdf;
+use crate::crawler::protection;
+use crate::crawler::readability;
+use crate::crawler::scholarly::ScholarlyMetadata;
+use crate::crawler::structured::{self, Entity};
+use crate::crawler::warc;
+
+/// Upper bound for `cleaned_text`, keeps index documents small
+pub const MAX_TEXT_CHARS: usize = 8000;
 
 #[derive(Debug, Clone, Default)]
 pub struct PageMetadata {
     pub url: String,
+    pub redirects: Vec<RedirectHop>, // hops from the requested url to `url`
     pub title: Option<String>,
     pub meta_description: Option<String>,
     pub canonical_url: Option<String>,
+    pub alternate_urls: Vec<String>, // fetched urls folded into this canonical one
     pub robots: Option<String>,
+    pub directives: Directives, // meta robots + X-Robots-Tag, for our user agent
     pub lang: Option<String>,
     pub h1: Option<String>,
+    pub author: Option<String>, // PDF document info
     pub og_title: Option<String>,
     pub og_description: Option<String>,
     pub og_image: Option<String>,
     pub og_url: Option<String>,
+    pub scholarly: ScholarlyMetadata, // citation_* / DC.* meta tags
+    pub entities: Vec<Entity>,        // schema.org JSON-LD / microdata
     pub content_type: Option<String>,
+    pub encoding: Option<String>, // charset the body was decoded from
     pub last_modified: Option<String>,
+    pub etag: Option<String>,
     pub server: Option<String>,
+    pub user_agent: Option<String>, // what we identified as for this fetch
     pub is_protected: bool,
     pub protection_reason: String,
     pub crawl_timestamp: i64,
     pub cleaned_text: Option<String>, // ✅ new field
+    pub simhash: Option<u64>,         // fingerprint of cleaned_text
+    pub cluster_id: Option<String>,   // representative url of the near-duplicate cluster
+    pub truncated: bool, // body hit MAX_BODY_BYTES, text/links come from the first part only
+    pub body_bytes: u64, // downloaded body size, charged to the domain's budget
+    pub depth: u32,      // link hops from a seed / sitemap / feed url
 }
 
 #[derive(Debug, Clone)]
 pub struct CrawlResult {
     pub metadata: PageMetadata,
     pub links: Vec<String>,
+    pub feeds: Vec<String>, // RSS / Atom feeds advertised with <link rel="alternate">
 }
 
-pub fn crawl_page(raw_url: &str) ->Result<Option<CrawlResult>, Box<dyn std::error::Error + Send + Sync>> {
+/// Cache validators from the previous crawl of a url, sent back on recrawl
+#[derive(Debug, Clone, Default)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum CrawlOutcome {
+    Fetched(Box<CrawlResult>),
+    NotModified, // 304 -> page unchanged since the last crawl
+}
+
+pub async fn crawl_page(raw_url: &str, validators: &Validators) -> Result<CrawlOutcome, FetchError> {
+    crawl_page_with(FETCHER.as_ref(), raw_url, validators).await
+}
+
+/// `crawl_page` against a given fetcher (e.g. `FixtureFetcher` in tests)
+pub async fn crawl_page_with(
+    fetcher: &dyn Fetcher,
+    raw_url: &str,
+    validators: &Validators,
+) -> Result<CrawlOutcome, FetchError> {
     let url = &clean_url(raw_url).unwrap_or_else(|| raw_url.to_string());
 
+    let response = fetcher.fetch(url, validators).await?;
 
-    let mut easy = Easy::new();
-    easy.url(url)?;
-    easy.follow_location(true)?;
-    easy.max_redirections(10)?;
-    easy.timeout(std::time::Duration::from_secs(30))?;
-    easy.accept_encoding("gzip, deflate")?;
-    easy.useragent(&random_ua())?;
-
-    // Headers
-    let mut headers = List::new();
-    headers.append("Accept: text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8")?;
-    headers.append("Accept-Language: en-US,en;q=0.9")?;
-    headers.append("Connection: keep-alive")?;
-    easy.http_headers(headers)?;
-
-    // Buffers
-    let mut html_bytes = Vec::new();
-    let mut response_headers = Vec::new();
-    let (mut status_code, mut content_type, mut server, mut last_modified) =
-        (0, None, None, None);
-
-    // === Perform request ===
-    {
-        let mut transfer = easy.transfer();
-        transfer.header_function(|h| {
-            response_headers.push(String::from_utf8_lossy(h).to_string());
-            true
-        })?;
-        transfer.write_function(|d| {
-            html_bytes.extend_from_slice(d);
-            Ok(d.len())
-        })?;
-        transfer.perform()?;
+    // HTML and especially PDF parsing is CPU work -> keep it off the async workers
+    tokio::task::spawn_blocking(move || {
+        let outcome = parse_page(&response);
+        let noarchive = matches!(&outcome, Ok(CrawlOutcome::Fetched(res)) if res.metadata.directives.noarchive);
+        if !noarchive {
+            warc::archive(&response);
+        }
+        outcome
+    })
+        .await
+        .map_err(|err| FetchError::Other(format!("parser task failed: {}", err)))?
+}
+
+/// Turns a fetched response into a `CrawlResult` (metadata, text, outlinks).
+/// The document is identified by the effective url, after redirects.
+pub fn parse_page(response: &FetchResponse) -> Result<CrawlOutcome, FetchError> {
+    let url = &clean_url(&response.url).unwrap_or_else(|| response.url.clone());
+    let status_code = response.status;
+    let content_type = response.header("content-type");
+    let server = response.header("server").map(|s| s.to_lowercase());
+    let last_modified = response.header("last-modified");
+    let etag = response.header("etag");
+    let retry_after = response.header("retry-after");
+    let user_agent = response
+        .request_headers
+        .get("user-agent")
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+    let x_robots_tags: Vec<&str> = response
+        .headers
+        .get_all("x-robots-tag")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .collect();
+
+    // === Unchanged since last crawl ===
+    if status_code == 304 {
+        println!("[UNCHANGED] {}", url);
+        return Ok(CrawlOutcome::NotModified);
     }
 
-    status_code = easy.response_code()? as i32;
-    content_type = easy.content_type()?.map(|s| s.to_string());
-
-    for line in &response_headers {
-        let line_lower = line.trim().to_lowercase();
-        if line_lower.starts_with("server:") {
-            server = line_lower.strip_prefix("server:").map(|s| s.trim().to_string());
-        } else if line_lower.starts_with("last-modified:") {
-            last_modified = line_lower
-                .strip_prefix("last-modified:")
-                .map(|s| s.trim().to_string());
-        }
+    // === Challenge / login / block pages served with an error status ===
+    if let Some(reason) = protection::detect_blocked_status(status_code, &response.headers, &response.body) {
+        return Err(FetchError::Protected(reason));
     }
 
-    // === Skip non-200 ===
+    // === 4xx / 5xx / rate limited ===
+    if let Some(err) = FetchError::from_status(status_code, retry_after.as_deref()) {
+        return Err(err);
+    }
     if status_code != 200 {
-        println!("[SKIP] {} -> HTTP {}", url, status_code);
-        return Ok(None);
+        return Err(FetchError::Other(format!("unexpected HTTP {}", status_code)));
+    }
+
+    // === PDF (papers) ===
+    let is_pdf = content_type
+        .as_deref()
+        .is_some_and(|ct| ct.to_lowercase().contains("application/pdf"));
+    if is_pdf {
+        if response.truncated {
+            return Err(FetchError::TooLarge(response.body.len() as u64));
+        }
+        let metadata = PageMetadata {
+            url: url.to_string(),
+            redirects: response.redirects.clone(),
+            content_type,
+            last_modified,
+            etag,
+            server,
+            user_agent,
+            directives: Directives::parse(&[], x_robots_tags.into_iter(), &CRAWLER_TOKEN),
+            protection_reason: "public".to_string(),
+            crawl_timestamp: Utc::now().timestamp(),
+            body_bytes: response.body.len() as u64,
+            ..Default::default()
+        };
+        return pdf::parse_pdf(&response.body, metadata).map(|res| CrawlOutcome::Fetched(Box::new(res)));
     }
 
     // === Non-HTML ===
@@ -100,12 +174,15 @@ pub fn crawl_page(raw_url: &str) ->Result<Option<CrawlResult>, Box<dyn std::erro
         .map(|ct| ct.contains("text/html"))
         .unwrap_or(false);
     if !is_html {
-        println!("[SKIP] {} -> Non-HTML ({:?})", url, content_type);
-        return Ok(None);
+        return Err(FetchError::NonHtml(content_type.unwrap_or_default()));
+    }
+
+    if response.truncated {
+        println!("✂️ Truncated {} at {} bytes", url, response.body.len());
     }
 
     // === Parse HTML ===
-    let html = String::from_utf8_lossy(&html_bytes).to_string();
+    let (html, encoding) = charset::decode_html(&response.body, content_type.as_deref(), url);
     let document = Html::parse_document(&html);
 
     let title = document
@@ -117,21 +194,24 @@ pub fn crawl_page(raw_url: &str) ->Result<Option<CrawlResult>, Box<dyn std::erro
     let mut canonical_url = None;
     let mut robots = None;
     let mut og = HashMap::new();
+    let mut named_meta = Vec::new();
 
     for meta in document.select(&Selector::parse("meta").unwrap()) {
         if let Some(name) = meta.attr("name").map(|s| s.to_lowercase()) {
+            if let Some(content) = meta.attr("content") {
+                named_meta.push((name.clone(), content.to_string()));
+            }
             match name.as_str() {
                 "description" => meta_description = meta.attr("content").map(|s| s.to_string()),
                 "robots" => robots = meta.attr("content").map(|s| s.to_string()),
                 _ => {}
             }
         }
-        if let Some(property) = meta.attr("property").map(|s| s.to_lowercase()) {
-            if property.starts_with("og:") {
-                if let Some(content) = meta.attr("content") {
-                    og.insert(property, content.to_string());
-                }
-            }
+        if let Some(property) = meta.attr("property").map(|s| s.to_lowercase())
+            && property.starts_with("og:")
+            && let Some(content) = meta.attr("content")
+        {
+            og.insert(property, content.to_string());
         }
     }
 
@@ -153,6 +233,10 @@ pub fn crawl_page(raw_url: &str) ->Result<Option<CrawlResult>, Box<dyn std::erro
         .and_then(|html| html.attr("lang"))
         .map(|s| s.to_string());
 
+    let directives = Directives::parse(&named_meta, x_robots_tags.into_iter(), &CRAWLER_TOKEN);
+    let scholarly = ScholarlyMetadata::from_meta(&named_meta, url);
+    let entities = structured::extract(&document, url);
+
     // === Extract links ===
     let mut links_set = HashSet::new();
     let base_url = Url::parse(url).ok();
@@ -182,43 +266,100 @@ pub fn crawl_page(raw_url: &str) ->Result<Option<CrawlResult>, Box<dyn std::erro
             cleaned_links.insert(cleaned);
         }
     }
-    let links: Vec<String> = cleaned_links.into_iter().collect();
+    let mut links: Vec<String> = cleaned_links.into_iter().collect();
+    if directives.nofollow {
+        println!("🚫 nofollow {}, dropping {} links", url, links.len());
+        links.clear();
+    }
+
+    // === Feed discovery ===
+    let mut feeds = Vec::new();
+    for elem in document.select(&Selector::parse("link[rel][href][type]").unwrap()) {
+        let rel = elem.attr("rel").unwrap_or("").to_lowercase();
+        let feed_type = elem.attr("type").unwrap_or("").to_lowercase();
+        if !rel.split_whitespace().any(|r| r == "alternate")
+            || !(feed_type.contains("rss+xml") || feed_type.contains("atom+xml"))
+        {
+            continue;
+        }
+        if let Some(feed) = elem
+            .attr("href")
+            .and_then(|href| resolve_url(url, href, &base_url))
+            .and_then(|resolved| Url::parse(&resolved).ok())
+            .map(|mut feed| {
+                // Not clean_url: feed urls are often just a query (`?feed=rss2`)
+                feed.set_fragment(None);
+                feed.to_string()
+            })
+            && !feeds.contains(&feed)
+        {
+            feeds.push(feed);
+        }
+    }
 
     // === Cleaned Text Extraction ===
-    let cleaned_text = extract_clean_text(&html);
+    let cleaned_text = readability::extract(&document);
+
+    // === Protection: blocked pages are errors, paywalled ones are kept but flagged ===
+    let protection = protection::detect(
+        url,
+        &response.headers,
+        &response.redirects,
+        &document,
+        &html,
+        title.as_deref(),
+        cleaned_text.chars().count(),
+    );
+    if let Some(reason) = protection
+        && reason != "paywall"
+    {
+        return Err(FetchError::Protected(reason));
+    }
 
     let mut metadata = PageMetadata {
         url: url.to_string(),
+        redirects: response.redirects.clone(),
         title,
         meta_description,
         canonical_url: canonical_url.clone(),
+        alternate_urls: Vec::new(),
         robots,
+        directives,
         lang,
         h1,
+        author: None,
         og_title: og.get("og:title").cloned(),
         og_description: og.get("og:description").cloned(),
         og_image: og.get("og:image").cloned(),
         og_url: og.get("og:url").cloned(),
+        scholarly,
+        entities,
         content_type,
+        encoding: Some(encoding.to_string()),
         last_modified,
+        etag,
         server,
-        is_protected: false,
-        protection_reason: "public".to_string(),
+        user_agent,
+        is_protected: protection.is_some(),
+        protection_reason: protection.unwrap_or("public").to_string(),
         crawl_timestamp: Utc::now().timestamp(),
         cleaned_text: Some(cleaned_text), // ✅ include text
-        ..Default::default()
+        simhash: None,
+        cluster_id: None,
+        truncated: response.truncated,
+        body_bytes: response.body.len() as u64,
+        depth: 0,
     };
 
     // Resolve canonical
-    if let Some(canonical) = &metadata.canonical_url {
-        if let Ok(base) = Url::parse(url) {
-            if let Ok(resolved) = base.join(canonical) {
-                metadata.canonical_url = Some(resolved.to_string());
-            }
-        }
+    if let Some(canonical) = &metadata.canonical_url
+        && let Ok(base) = Url::parse(url)
+        && let Ok(resolved) = base.join(canonical)
+    {
+        metadata.canonical_url = Some(resolved.to_string());
     }
 
-    Ok(Some(CrawlResult { metadata, links }))
+    Ok(CrawlOutcome::Fetched(Box::new(CrawlResult { metadata, links, feeds })))
 }
 
 fn resolve_url(base_str: &str, href: &str, fallback_base: &Option<Url>) -> Option<String> {
@@ -233,90 +374,15 @@ fn resolve_url(base_str: &str, href: &str, fallback_base: &Option<Url>) -> Optio
     if let Ok(url) = Url::parse(trimmed) {
         return Some(url.to_string());
     }
-    if let Ok(base) = Url::parse(base_str) {
-        if let Ok(joined) = base.join(trimmed) {
-            return Some(joined.to_string());
-        }
+    if let Ok(base) = Url::parse(base_str)
+        && let Ok(joined) = base.join(trimmed)
+    {
+        return Some(joined.to_string());
     }
-    if let Some(base) = fallback_base {
-        if let Ok(joined) = base.join(trimmed) {
-            return Some(joined.to_string());
-        }
+    if let Some(base) = fallback_base
+        && let Ok(joined) = base.join(trimmed)
+    {
+        return Some(joined.to_string());
     }
     None
 }
-
-// use scraper::{Html, Selector};
-
-/// Extracts visible, human-readable text (no HTML, no CSS, no JS)
-fn extract_clean_text(html: &str) -> String {
-    // Parse the document
-    let document = Html::parse_document(html);
-
-    // Remove all <script>, <style>, <noscript>, <iframe>, <svg>, etc. first
-    let skip_tags = [
-        "script",
-        "style",
-        "noscript",
-        "iframe",
-        "canvas",
-        "svg",
-        "meta",
-        "link",
-        "button",
-        "input",
-        "form",
-        "nav",
-        "footer",
-        "header",
-    ];
-
-    // Collect text only from content-bearing elements
-    let mut text = String::new();
-    for selector in ["p", "article", "section", "main", "div", "span", "li"].iter() {
-        let sel = Selector::parse(selector).unwrap();
-        for elem in document.select(&sel) {
-            // Skip if this element (or its parent) is inside one of the skip tags
-            let mut inside_skipped = false;
-            let mut parent = elem.parent();
-            while let Some(p) = parent {
-                if let Some(el) = p.value().as_element() {
-                    if skip_tags.contains(&el.name()) {
-                        inside_skipped = true;
-                        break;
-                    }
-                }
-                parent = p.parent();
-            }
-            if inside_skipped {
-                continue;
-            }
-
-            // Extract visible text nodes
-            for txt in elem.text() {
-                let t = txt.trim();
-                if !t.is_empty()
-                    && !t.starts_with('{')
-                    && !t.ends_with('}')
-                    && !t.contains("var(")
-                    && !t.contains(':') // filter out CSS rules
-                    && !t.contains(';')
-                {
-                    text.push_str(t);
-                    text.push(' ');
-                }
-            }
-        }
-    }
-
-    // Normalize whitespace
-    let cleaned = text
-        .split_whitespace()
-        .collect::<Vec<_>>()
-        .join(" ")
-        .trim()
-        .to_string();
-
-    // Limit to prevent enormous pages
-    cleaned.chars().take(8000).collect()
-}
@@ -0,0 +1,122 @@
+/// Page-level robots directives, from `<meta name="robots">` and the `X-Robots-Tag` header
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Directives {
+    pub noindex: bool,   // keep out of Quickwit
+    pub nofollow: bool,  // no outlinks to the frontier or to backlink scores
+    pub noarchive: bool, // not written to the WARC store, no cached copy in search
+    pub nosnippet: bool, // search results show no text from the page
+}
+
+/// X-Robots-Tag directives that carry a value, so `name: value` is not a user-agent scope
+const VALUED_DIRECTIVES: [&str; 4] = ["unavailable_after", "max-snippet", "max-image-preview", "max-video-preview"];
+
+impl Directives {
+    /// Directives for `agent` (a robots.txt product token, `*` = no specific name).
+    /// `meta` is every `(name, content)` meta tag, `headers` every X-Robots-Tag value.
+    pub fn parse<'a>(meta: &[(String, String)], headers: impl Iterator<Item = &'a str>, agent: &str) -> Self {
+        let agent = agent.to_lowercase();
+        let mut directives = Directives::default();
+
+        // <meta name="robots"> applies to everyone, <meta name="<token>"> to that crawler only
+        for (name, content) in meta {
+            let name = name.to_lowercase();
+            if name == "robots" || (agent != "*" && name == agent) {
+                for directive in content.split(',') {
+                    directives.apply(directive);
+                }
+            }
+        }
+
+        // "noindex, nofollow" or "googlebot: noindex" / "otherbot: noindex, nofollow"
+        for header in headers {
+            let mut applies = true;
+            for part in header.split(',') {
+                let mut directive = part.trim();
+                if let Some((prefix, rest)) = directive.split_once(':') {
+                    let prefix = prefix.trim().to_lowercase();
+                    if !VALUED_DIRECTIVES.contains(&prefix.as_str()) {
+                        applies = agent != "*" && prefix == agent;
+                        directive = rest.trim();
+                    }
+                }
+                if applies {
+                    directives.apply(directive);
+                }
+            }
+        }
+
+        directives
+    }
+
+    fn apply(&mut self, directive: &str) {
+        let directive = directive.trim().to_lowercase();
+        match directive.as_str() {
+            "noindex" => self.noindex = true,
+            "nofollow" => self.nofollow = true,
+            "noarchive" | "nocache" => self.noarchive = true,
+            "nosnippet" => self.nosnippet = true,
+            "none" => {
+                self.noindex = true;
+                self.nofollow = true;
+            }
+            _ => {
+                // max-snippet:0 is nosnippet spelled differently
+                if let Some((name, value)) = directive.split_once(':')
+                    && name.trim() == "max-snippet"
+                    && value.trim() == "0"
+                {
+                    self.nosnippet = true;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(tags: &[(&str, &str)]) -> Vec<(String, String)> {
+        tags.iter().map(|(name, content)| (name.to_string(), content.to_string())).collect()
+    }
+
+    #[test]
+    fn meta_robots_applies_to_everyone_and_named_tags_to_their_crawler() {
+        let tags = meta(&[("robots", "NoIndex, nofollow"), ("researchbot", "noarchive"), ("otherbot", "nosnippet")]);
+        let directives = Directives::parse(&tags, std::iter::empty(), "ReSearchBot");
+        assert_eq!(
+            directives,
+            Directives { noindex: true, nofollow: true, noarchive: true, nosnippet: false }
+        );
+
+        // `*` has no name of its own
+        let directives = Directives::parse(&meta(&[("researchbot", "noindex")]), std::iter::empty(), "*");
+        assert_eq!(directives, Directives::default());
+    }
+
+    #[test]
+    fn headers_are_scoped_by_their_agent_prefix() {
+        let headers = ["nosnippet", "otherbot: noindex, nofollow", "researchbot: noarchive"];
+        let directives = Directives::parse(&[], headers.into_iter(), "researchbot");
+        assert_eq!(
+            directives,
+            Directives { noindex: false, nofollow: false, noarchive: true, nosnippet: true }
+        );
+    }
+
+    #[test]
+    fn valued_directives_are_not_agent_scopes() {
+        let headers = ["max-snippet: 0, noindex", "unavailable_after: 2030-01-01, nocache"];
+        let directives = Directives::parse(&[], headers.into_iter(), "researchbot");
+        assert_eq!(
+            directives,
+            Directives { noindex: true, nofollow: false, noarchive: true, nosnippet: true }
+        );
+
+        let directives = Directives::parse(&meta(&[("robots", "none, max-snippet:50")]), std::iter::empty(), "*");
+        assert_eq!(
+            directives,
+            Directives { noindex: true, nofollow: true, noarchive: false, nosnippet: false }
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use chrono::Utc;
+use r2d2_redis::redis::{RedisResult, cmd, pipe};
+use reqwest::StatusCode;
+use reqwest::header::{HeaderMap, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH, USER_AGENT};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::sleep;
+use url::Url;
+
+use crate::common::config::{
+    FEED_BATCH, FEED_DEFAULT_INTERVAL_SECS, FEED_LEASE_SECS, FEED_MAX_INTERVAL_SECS, FEED_MAX_PARSE_FAILURES,
+    FEED_MIN_INTERVAL_SECS, MAX_BODY_BYTES, CRAWLER_TOKEN,
+};
+use crate::common::utils::user_agent;
+use crate::crawler::clean_url;
+use crate::crawler::core::{Priority, enqueue_and_mark_seen};
+use crate::crawler::fetch::{get_following, read_body_capped};
+use crate::crawler::{politeness, robots, utils};
+use crate::db::{get_kv_conn, paths};
+
+/// Adds feeds advertised by `page_url` to the registry; known feeds keep their schedule
+pub fn register_feeds(feeds: &[String], page_url: &str) {
+    if feeds.is_empty() {
+        return;
+    }
+
+    let now = Utc::now().timestamp();
+    let mut kv_conn = get_kv_conn();
+    let mut pipeline = pipe();
+
+    for feed in feeds {
+        let key = format!("{}:{}", paths::FEEDS, feed);
+        pipeline.cmd("HSETNX").arg(&key).arg("page").arg(page_url).ignore();
+        pipeline.cmd("HSETNX").arg(&key).arg("added_at").arg(now).ignore();
+        // NX: a feed that is already scheduled keeps its next poll time
+        pipeline.cmd("ZADD").arg(paths::FEED_QUEUE).arg("NX").arg(now).arg(feed).ignore();
+    }
+
+    if let Err(err) = pipeline.query::<()>(&mut *kv_conn) {
+        eprintln!("⚠️ Failed to register feeds for {}: {}", page_url, err);
+    }
+}
+
+/// Polls due feeds forever; new items are queued with high priority
+pub async fn poll_loop() {
+    loop {
+        let due = claim_due_feeds();
+        if due.is_empty() {
+            sleep(Duration::from_secs(30)).await;
+            continue;
+        }
+
+        for feed in due {
+            let next_in = poll_feed(&feed).await;
+            schedule(&feed, next_in);
+        }
+    }
+}
+
+/// Takes up to FEED_BATCH due feeds. Each one is leased (pushed FEED_LEASE_SECS into the future)
+/// so another process doesn't poll it too, and so it comes back if we die mid-poll.
+fn claim_due_feeds() -> Vec<String> {
+    let now = Utc::now().timestamp();
+    let mut kv_conn = get_kv_conn();
+
+    let due: Vec<String> = cmd("ZRANGEBYSCORE")
+        .arg(paths::FEED_QUEUE)
+        .arg("-inf")
+        .arg(now)
+        .arg("LIMIT")
+        .arg(0)
+        .arg(FEED_BATCH)
+        .query(&mut *kv_conn)
+        .unwrap_or_default();
+
+    due.into_iter()
+        .filter(|feed| {
+            let removed: i64 = cmd("ZREM")
+                .arg(paths::FEED_QUEUE)
+                .arg(feed)
+                .query(&mut *kv_conn)
+                .unwrap_or(0);
+            if removed == 1 {
+                let _: RedisResult<()> = cmd("ZADD")
+                    .arg(paths::FEED_QUEUE)
+                    .arg(now + FEED_LEASE_SECS as i64)
+                    .arg(feed)
+                    .query(&mut *kv_conn);
+            }
+            removed == 1
+        })
+        .collect()
+}
+
+fn schedule(feed: &str, next_in: Option<u64>) {
+    let mut kv_conn = get_kv_conn();
+    match next_in {
+        Some(secs) => {
+            let _: RedisResult<()> = cmd("ZADD")
+                .arg(paths::FEED_QUEUE)
+                .arg(Utc::now().timestamp() + secs as i64)
+                .arg(feed)
+                .query(&mut *kv_conn);
+        }
+        // Gone for good (404 / 410, never parses) -> drop from the schedule
+        None => {
+            let _: RedisResult<()> = cmd("ZREM").arg(paths::FEED_QUEUE).arg(feed).query(&mut *kv_conn);
+        }
+    }
+}
+
+/// Fetches and parses one feed. Returns seconds until the next poll, `None` to unregister it:
+/// only on 404 / 410 or after FEED_MAX_PARSE_FAILURES unparseable polls in a row. Network and
+/// body read errors, robots.txt disallows and 5xx just wait for the next poll.
+async fn poll_feed(feed: &str) -> Option<u64> {
+    let key = format!("{}:{}", paths::FEEDS, feed);
+    let state: HashMap<String, String> = {
+        let mut kv_conn = get_kv_conn();
+        cmd("HGETALL").arg(&key).query(&mut *kv_conn).unwrap_or_default()
+    };
+    let interval: u64 = state
+        .get("interval_secs")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(FEED_DEFAULT_INTERVAL_SECS);
+
+    let parsed = Url::parse(feed).ok()?;
+    let rules = robots::rules_for(&parsed).await;
+    if let Some(reason) = rules.disallow_reason(&CRAWLER_TOKEN, &parsed) {
+        utils::record_skip(feed, &reason);
+        return Some(FEED_MAX_INTERVAL_SECS); // robots.txt may change, check again tomorrow
+    }
+    politeness::wait_for_slot(parsed.host_str().unwrap_or_default(), politeness::host_delay_ms(&rules)).await;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(USER_AGENT, HeaderValue::from_static(user_agent(feed)));
+    let mut conditional = HeaderMap::new();
+    if let Some(etag) = state.get("etag").and_then(|v| HeaderValue::from_str(v).ok()) {
+        conditional.insert(IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = state.get("last_modified").and_then(|v| HeaderValue::from_str(v).ok()) {
+        conditional.insert(IF_MODIFIED_SINCE, last_modified);
+    }
+
+    let (mut resp, _) = match get_following(feed, headers, conditional, Some(Duration::from_secs(30))).await {
+        Ok(resp) => resp,
+        Err(err) => {
+            println!("⚠️ Feed {} failed: {}", feed, err);
+            return Some(interval);
+        }
+    };
+
+    let status = resp.status();
+    if status == StatusCode::NOT_MODIFIED {
+        touch_polled(&key);
+        return Some(interval);
+    }
+    if matches!(status, StatusCode::NOT_FOUND | StatusCode::GONE) {
+        println!("🗑️ Feed {} gone (HTTP {})", feed, status);
+        return None;
+    }
+    if !status.is_success() {
+        return Some(interval);
+    }
+
+    let etag = header_string(resp.headers(), "etag");
+    let last_modified = header_string(resp.headers(), "last-modified");
+    let body = match read_body_capped(&mut resp, *MAX_BODY_BYTES).await {
+        Ok((body, _)) => body,
+        Err(err) => {
+            println!("⚠️ Feed {} body failed: {}", feed, err);
+            return Some(interval);
+        }
+    };
+
+    let parsed_feed = match feed_rs::parser::parse(&body[..]) {
+        Ok(parsed_feed) => parsed_feed,
+        Err(err) => {
+            let failures = count_parse_failure(&key);
+            println!("⚠️ Not a feed {} ({} in a row): {}", feed, failures, err);
+            return (failures < FEED_MAX_PARSE_FAILURES).then_some(interval);
+        }
+    };
+
+    // Item pages: the entry's alternate link (or the first one)
+    let items: Vec<String> = parsed_feed
+        .entries
+        .iter()
+        .filter_map(|entry| {
+            entry
+                .links
+                .iter()
+                .find(|l| l.rel.as_deref().is_none_or(|rel| rel == "alternate"))
+                .or(entry.links.first())
+                .and_then(|l| parsed.join(&l.href).ok())
+                .and_then(|u| clean_url(u.as_str()))
+        })
+        .collect();
+
+    let dates: Vec<i64> = parsed_feed
+        .entries
+        .iter()
+        .filter_map(|entry| entry.published.or(entry.updated).map(|d| d.timestamp()))
+        .collect();
+    let cadence = cadence_secs(&dates);
+    let next_interval = cadence
+        .map(|c| (c / 2).clamp(FEED_MIN_INTERVAL_SECS, FEED_MAX_INTERVAL_SECS))
+        .unwrap_or(FEED_DEFAULT_INTERVAL_SECS);
+
+    println!(
+        "📰 Feed {} -> {} items, cadence {:?}s, next poll in {}s",
+        feed,
+        items.len(),
+        cadence,
+        next_interval
+    );
+
+    let new_urls = utils::hash_links(&items).unwrap_or_default();
+    let _ = tokio::task::spawn_blocking(move || {
+        let mut kv_conn = get_kv_conn();
+        enqueue_and_mark_seen(&new_urls, Priority::High, 0, &mut kv_conn)
+    })
+    .await;
+
+    // Registry entry: when we polled, how often it publishes, validators for the next poll
+    let mut kv_conn = get_kv_conn();
+    let mut pipeline = pipe();
+    pipeline.cmd("HSET").arg(&key).arg("last_polled").arg(Utc::now().timestamp()).ignore();
+    pipeline.cmd("HSET").arg(&key).arg("interval_secs").arg(next_interval).ignore();
+    pipeline.cmd("HSET").arg(&key).arg("items").arg(items.len()).ignore();
+    pipeline.cmd("HDEL").arg(&key).arg("parse_failures").ignore();
+    if let Some(cadence) = cadence {
+        pipeline.cmd("HSET").arg(&key).arg("cadence_secs").arg(cadence).ignore();
+    }
+    if let Some(latest) = dates.iter().max() {
+        pipeline.cmd("HSET").arg(&key).arg("last_item_at").arg(*latest).ignore();
+    }
+    for (field, value) in [("etag", etag), ("last_modified", last_modified)] {
+        match value {
+            Some(value) => pipeline.cmd("HSET").arg(&key).arg(field).arg(value).ignore(),
+            None => pipeline.cmd("HDEL").arg(&key).arg(field).ignore(),
+        };
+    }
+    let _: RedisResult<()> = pipeline.query(&mut *kv_conn);
+
+    Some(next_interval)
+}
+
+/// Average gap between consecutive items (newest 20), `None` with fewer than two dated items
+fn cadence_secs(dates: &[i64]) -> Option<u64> {
+    let mut sorted = dates.to_vec();
+    sorted.sort_unstable_by(|a, b| b.cmp(a));
+    sorted.dedup();
+    sorted.truncate(20);
+
+    if sorted.len() < 2 {
+        return None;
+    }
+    let span = sorted.first()? - sorted.last()?;
+    Some((span / (sorted.len() as i64 - 1)).max(0) as u64)
+}
+
+/// One more unparseable poll in a row; returns the streak
+fn count_parse_failure(key: &str) -> u64 {
+    let mut kv_conn = get_kv_conn();
+    cmd("HINCRBY")
+        .arg(key)
+        .arg("parse_failures")
+        .arg(1)
+        .query(&mut *kv_conn)
+        .unwrap_or(1)
+}
+
+fn touch_polled(key: &str) {
+    let mut kv_conn = get_kv_conn();
+    let _: RedisResult<()> = cmd("HSET")
+        .arg(key)
+        .arg("last_polled")
+        .arg(Utc::now().timestamp())
+        .query(&mut *kv_conn);
+}
+
+fn header_string(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_string())
+}
@@ -0,0 +1,316 @@
+use once_cell::sync::Lazy;
+use reqwest::header::{
+    ACCEPT, ACCEPT_LANGUAGE, CONTENT_TYPE, HeaderMap, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+    LOCATION, USER_AGENT,
+};
+use reqwest::{Client, Response, StatusCode, redirect};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use crate::common::config::MAX_BODY_BYTES;
+use crate::common::utils::user_agent;
+use crate::crawler::crawl::Validators;
+use crate::crawler::politeness;
+
+pub const MAX_REDIRECTS: usize = 10;
+
+/// One shared client for the whole crawler: keep-alive connections are pooled per host
+/// (HTTP/1.1 and HTTP/2 via ALPN), so concurrent fetches to a host reuse sockets.
+/// Redirects are followed by hand (`get_following`) so every hop can be recorded.
+pub static HTTP_CLIENT: Lazy<Client> = Lazy::new(|| {
+    Client::builder()
+        .timeout(Duration::from_secs(30))
+        .connect_timeout(Duration::from_secs(10))
+        .pool_idle_timeout(Duration::from_secs(90))
+        .pool_max_idle_per_host(16)
+        .tcp_keepalive(Duration::from_secs(60))
+        .redirect(redirect::Policy::none())
+        .gzip(true)
+        .deflate(true)
+        .brotli(true)
+        .build()
+        .expect("Failed to build HTTP client")
+});
+
+/// Why a page could not be fetched; decides whether it is worth retrying
+#[derive(Debug)]
+pub enum FetchError {
+    Dns(String),
+    Connect(String),
+    Tls(String),
+    Timeout,
+    ClientError(u16), // 4xx
+    ServerError(u16), // 5xx
+    RateLimited { status: u16, retry_after: Duration },
+    TooLarge(u64),
+    NonHtml(String),
+    Protected(&'static str), // challenge / login / denied page instead of the content
+    Other(String),
+}
+
+impl FetchError {
+    /// Transient failures worth another attempt later
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            FetchError::Dns(_)
+                | FetchError::Connect(_)
+                | FetchError::Timeout
+                | FetchError::ServerError(_)
+                | FetchError::RateLimited { .. }
+        )
+    }
+
+    /// Short label used for stats keys and skip reasons
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FetchError::Dns(_) => "dns",
+            FetchError::Connect(_) => "connect",
+            FetchError::Tls(_) => "tls",
+            FetchError::Timeout => "timeout",
+            FetchError::ClientError(_) => "4xx",
+            FetchError::ServerError(_) => "5xx",
+            FetchError::RateLimited { .. } => "rate_limited",
+            FetchError::TooLarge(_) => "too_large",
+            FetchError::NonHtml(_) => "non_html",
+            FetchError::Protected(_) => "protected",
+            FetchError::Other(_) => "other",
+        }
+    }
+
+    /// Maps an error HTTP status to its class (`None` for statuses that aren't errors)
+    pub fn from_status(status: u16, retry_after: Option<&str>) -> Option<FetchError> {
+        match status {
+            429 => Some(FetchError::RateLimited {
+                status,
+                retry_after: politeness::parse_retry_after(retry_after),
+            }),
+            503 if retry_after.is_some() => Some(FetchError::RateLimited {
+                status,
+                retry_after: politeness::parse_retry_after(retry_after),
+            }),
+            400..=499 => Some(FetchError::ClientError(status)),
+            500..=599 => Some(FetchError::ServerError(status)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Dns(msg) => write!(f, "dns: {}", msg),
+            FetchError::Connect(msg) => write!(f, "connect: {}", msg),
+            FetchError::Tls(msg) => write!(f, "tls: {}", msg),
+            FetchError::Timeout => write!(f, "timeout"),
+            FetchError::ClientError(status) | FetchError::ServerError(status) => write!(f, "HTTP {}", status),
+            FetchError::RateLimited { status, retry_after } => {
+                write!(f, "HTTP {} (retry after {}s)", status, retry_after.as_secs())
+            }
+            FetchError::TooLarge(bytes) => write!(f, "too large ({} bytes)", bytes),
+            FetchError::NonHtml(content_type) => write!(f, "non-html ({})", content_type),
+            FetchError::Protected(reason) => write!(f, "protected ({})", reason),
+            FetchError::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl Error for FetchError {}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            return FetchError::Timeout;
+        }
+
+        // reqwest wraps hyper / the resolver / the TLS stack -> look at the whole chain
+        let mut chain = err.to_string();
+        let mut source = err.source();
+        while let Some(inner) = source {
+            chain.push_str(": ");
+            chain.push_str(&inner.to_string());
+            source = inner.source();
+        }
+        let lower = chain.to_lowercase();
+
+        if lower.contains("dns error") || lower.contains("failed to lookup address") {
+            FetchError::Dns(chain)
+        } else if lower.contains("certificate") || lower.contains("tls") || lower.contains("ssl") || lower.contains("handshake") {
+            FetchError::Tls(chain)
+        } else if err.is_connect() || err.is_body() || err.is_decode() || err.is_request() {
+            // refused / reset / dropped mid-body: all transient
+            FetchError::Connect(chain)
+        } else {
+            FetchError::Other(chain)
+        }
+    }
+}
+
+/// One redirect on the way to the final document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedirectHop {
+    pub url: String,
+    pub status: u16,
+    pub location: String,
+}
+
+/// Raw HTTP response handed to the page parser
+#[derive(Debug, Clone)]
+pub struct FetchResponse {
+    pub url: String, // effective url after redirects
+    pub request_headers: HeaderMap, // what we sent for `url`
+    pub redirects: Vec<RedirectHop>,
+    pub status: u16,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+    pub truncated: bool, // body cut at MAX_BODY_BYTES
+}
+
+impl FetchResponse {
+    /// Header value as a string (original case preserved)
+    pub fn header(&self, name: &str) -> Option<String> {
+        self.headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_string())
+    }
+}
+
+/// GETs `url`, following up to MAX_REDIRECTS redirects by hand.
+///
+/// `first_hop_headers` (conditional headers) only go to the requested url; `headers` go everywhere.
+pub async fn get_following(
+    url: &str,
+    headers: HeaderMap,
+    first_hop_headers: HeaderMap,
+    timeout: Option<Duration>,
+) -> Result<(Response, Vec<RedirectHop>), reqwest::Error> {
+    let mut redirects = Vec::new();
+    let mut current = url.to_string();
+    let mut extra = first_hop_headers;
+
+    loop {
+        let mut request = HTTP_CLIENT
+            .get(&current)
+            .headers(headers.clone())
+            .headers(std::mem::take(&mut extra));
+        if let Some(timeout) = timeout {
+            request = request.timeout(timeout);
+        }
+        let resp = request.send().await?;
+
+        let status = resp.status();
+        let next = if status.is_redirection() && status != StatusCode::NOT_MODIFIED {
+            resp.headers()
+                .get(LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|location| resp.url().join(location).ok())
+        } else {
+            None
+        };
+
+        match next {
+            Some(next) if redirects.len() < MAX_REDIRECTS => {
+                redirects.push(RedirectHop {
+                    url: current,
+                    status: status.as_u16(),
+                    location: next.to_string(),
+                });
+                current = next.to_string();
+            }
+            // Final document (or redirect limit hit: the 3xx itself is returned)
+            _ => return Ok((resp, redirects)),
+        }
+    }
+}
+
+pub async fn fetch(url: &str, validators: &Validators) -> Result<FetchResponse, FetchError> {
+    let mut headers = HeaderMap::new();
+    headers.insert(USER_AGENT, HeaderValue::from_static(user_agent(url)));
+    headers.insert(
+        ACCEPT,
+        HeaderValue::from_static("text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"),
+    );
+    headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_static("en-US,en;q=0.9"));
+
+    // Validators belong to the requested url, not to whatever it redirects to
+    let mut conditional = HeaderMap::new();
+    if let Some(etag) = validators.etag.as_deref().and_then(|v| HeaderValue::from_str(v).ok()) {
+        conditional.insert(IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = validators
+        .last_modified
+        .as_deref()
+        .and_then(|v| HeaderValue::from_str(v).ok())
+    {
+        conditional.insert(IF_MODIFIED_SINCE, last_modified);
+    }
+
+    let mut request_headers = headers.clone();
+    let (mut resp, redirects) = get_following(url, headers, conditional.clone(), None).await?;
+    if redirects.is_empty() {
+        request_headers.extend(conditional);
+    }
+    let final_url = resp.url().to_string();
+    let status = resp.status().as_u16();
+    let headers = resp.headers().clone();
+
+    // Don't download documents we can't use
+    let max_bytes = *MAX_BODY_BYTES;
+    if resp.status().is_success() {
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|ct| ct.to_lowercase());
+        if !is_wanted_content_type(content_type.as_deref()) {
+            return Err(FetchError::NonHtml(content_type.unwrap_or_default()));
+        }
+
+        // A cut-off HTML page is still useful, a cut-off document isn't -> don't even start
+        let is_html = content_type.as_deref().is_none_or(|ct| ct.contains("html"));
+        if let Some(length) = resp.content_length()
+            && length > max_bytes as u64
+            && !is_html
+        {
+            return Err(FetchError::TooLarge(length));
+        }
+    }
+
+    let (body, truncated) = read_body_capped(&mut resp, max_bytes).await?;
+
+    Ok(FetchResponse {
+        url: final_url,
+        request_headers,
+        redirects,
+        status,
+        headers,
+        body,
+        truncated,
+    })
+}
+
+/// Streams the body and stops at `max_bytes` instead of buffering whatever the server sends.
+/// Returns the body and whether it was cut.
+pub async fn read_body_capped(resp: &mut Response, max_bytes: usize) -> Result<(Vec<u8>, bool), reqwest::Error> {
+    let mut body = Vec::new();
+    while let Some(chunk) = resp.chunk().await? {
+        let room = max_bytes - body.len();
+        if chunk.len() > room {
+            body.extend_from_slice(&chunk[..room]);
+            return Ok((body, true)); // dropping `resp` aborts the transfer
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok((body, false))
+}
+
+/// HTML and PDF (or an unknown type) are worth downloading, anything else is aborted after the headers.
+/// Expects a lowercased content type.
+pub fn is_wanted_content_type(content_type: Option<&str>) -> bool {
+    match content_type {
+        None => true,
+        Some(ct) => ct.contains("html") || ct.contains("pdf"),
+    }
+}
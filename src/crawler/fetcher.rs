@@ -0,0 +1,318 @@
+use once_cell::sync::Lazy;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::collections::HashMap;
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use url::Url;
+
+use crate::common::config::{FIXTURE_DIR, MAX_BODY_BYTES};
+use crate::crawler::clean_url;
+use crate::crawler::crawl::Validators;
+use crate::crawler::fetch::{self, FetchError, FetchResponse, MAX_REDIRECTS, RedirectHop, is_wanted_content_type};
+
+pub type FetchFuture<'a> = Pin<Box<dyn Future<Output = Result<FetchResponse, FetchError>> + Send + 'a>>;
+
+/// Where `crawl_page` gets its pages from
+pub trait Fetcher: Send + Sync {
+    fn fetch<'a>(&'a self, url: &'a str, validators: &'a Validators) -> FetchFuture<'a>;
+}
+
+/// The crawler's fetcher: recorded responses when FIXTURE_DIR is set, the network otherwise
+pub static FETCHER: Lazy<Box<dyn Fetcher>> = Lazy::new(|| match FIXTURE_DIR.as_deref() {
+    Some(dir) => {
+        let fixtures = FixtureFetcher::open(dir).expect("Failed to load FIXTURE_DIR");
+        println!("🧪 Serving {} recorded pages from {}", fixtures.len(), dir);
+        Box::new(fixtures)
+    }
+    None => Box::new(HttpFetcher),
+});
+
+/// Real HTTP through the shared reqwest client
+pub struct HttpFetcher;
+
+impl Fetcher for HttpFetcher {
+    fn fetch<'a>(&'a self, url: &'a str, validators: &'a Validators) -> FetchFuture<'a> {
+        Box::pin(fetch::fetch(url, validators))
+    }
+}
+
+/// One recorded response
+#[derive(Debug, Clone)]
+struct Recorded {
+    status: u16,
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+/// Serves responses recorded in a directory of `*.http` files, no network involved.
+///
+/// Each file is the requested url on the first line followed by a raw HTTP response:
+///
+/// ```text
+/// https://example.org/old
+/// HTTP/1.1 301 Moved Permanently
+/// Location: /new
+///
+/// ```
+///
+/// Redirects are followed, `If-None-Match` / `If-Modified-Since` get a 304 when they match
+/// the recorded `ETag` / `Last-Modified`, and unknown urls are a 404.
+pub struct FixtureFetcher {
+    responses: HashMap<String, Recorded>,
+}
+
+impl FixtureFetcher {
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let mut responses = HashMap::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "http") {
+                continue;
+            }
+            let raw = fs::read(&path)?;
+            let (url, recorded) = parse_recorded(&raw).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("bad fixture {}", path.display()))
+            })?;
+            responses.insert(url, recorded);
+        }
+        Ok(FixtureFetcher { responses })
+    }
+
+    pub fn len(&self) -> usize {
+        self.responses.len()
+    }
+
+    /// Exact url first, then its `clean_url` form (what the crawler asks for)
+    fn lookup(&self, url: &str) -> Option<&Recorded> {
+        self.responses
+            .get(url)
+            .or_else(|| clean_url(url).and_then(|cleaned| self.responses.get(&cleaned)))
+    }
+
+    fn serve(&self, url: &str, validators: &Validators) -> Result<FetchResponse, FetchError> {
+        let not_found = Recorded {
+            status: 404,
+            headers: HeaderMap::new(),
+            body: Vec::new(),
+        };
+
+        let mut redirects = Vec::new();
+        let mut current = url.to_string();
+        loop {
+            let recorded = self.lookup(&current).unwrap_or(&not_found);
+
+            // Validators go to the requested url only, like `fetch`
+            if redirects.is_empty() && is_unchanged(recorded, validators) {
+                return Ok(FetchResponse {
+                    url: current,
+                    request_headers: HeaderMap::new(),
+                    redirects,
+                    status: 304,
+                    headers: recorded.headers.clone(),
+                    body: Vec::new(),
+                    truncated: false,
+                });
+            }
+
+            let location = recorded
+                .headers
+                .get("location")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|location| Url::parse(&current).ok()?.join(location).ok());
+            match location {
+                Some(next) if (300..400).contains(&recorded.status) && redirects.len() < MAX_REDIRECTS => {
+                    redirects.push(RedirectHop {
+                        url: current,
+                        status: recorded.status,
+                        location: next.to_string(),
+                    });
+                    current = next.to_string();
+                }
+                _ => {
+                    let content_type = recorded
+                        .headers
+                        .get("content-type")
+                        .and_then(|v| v.to_str().ok())
+                        .map(|ct| ct.to_lowercase());
+                    if (200..300).contains(&recorded.status) && !is_wanted_content_type(content_type.as_deref()) {
+                        return Err(FetchError::NonHtml(content_type.unwrap_or_default()));
+                    }
+
+                    let max_bytes = *MAX_BODY_BYTES;
+                    let truncated = recorded.body.len() > max_bytes;
+                    return Ok(FetchResponse {
+                        url: current,
+                        request_headers: HeaderMap::new(),
+                        redirects,
+                        status: recorded.status,
+                        headers: recorded.headers.clone(),
+                        body: recorded.body[..recorded.body.len().min(max_bytes)].to_vec(),
+                        truncated,
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl Fetcher for FixtureFetcher {
+    fn fetch<'a>(&'a self, url: &'a str, validators: &'a Validators) -> FetchFuture<'a> {
+        Box::pin(async move { self.serve(url, validators) })
+    }
+}
+
+fn is_unchanged(recorded: &Recorded, validators: &Validators) -> bool {
+    let header = |name: &str| recorded.headers.get(name).and_then(|v| v.to_str().ok());
+    matches!((validators.etag.as_deref(), header("etag")), (Some(sent), Some(current)) if sent == current)
+        || matches!(
+            (validators.last_modified.as_deref(), header("last-modified")),
+            (Some(sent), Some(current)) if sent == current
+        )
+}
+
+/// Splits a fixture file into its url and response. The body is kept byte for byte.
+fn parse_recorded(raw: &[u8]) -> Option<(String, Recorded)> {
+    let mut rest = raw;
+    let mut next_line = || {
+        let end = rest.iter().position(|&b| b == b'\n')?;
+        let line = String::from_utf8_lossy(&rest[..end]).trim_end_matches('\r').to_string();
+        rest = &rest[end + 1..];
+        Some(line)
+    };
+
+    let url = next_line()?.trim().to_string();
+    let status: u16 = next_line()?.split_whitespace().nth(1)?.parse().ok()?;
+
+    let mut headers = HeaderMap::new();
+    loop {
+        let line = next_line().unwrap_or_default();
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':')?;
+        headers.append(
+            HeaderName::from_bytes(name.trim().as_bytes()).ok()?,
+            HeaderValue::from_str(value.trim()).ok()?,
+        );
+    }
+
+    let recorded = Recorded {
+        status,
+        headers,
+        body: rest.to_vec(),
+    };
+    Some((url, recorded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crawler::crawl::{CrawlOutcome, crawl_page_with};
+
+    fn fixtures() -> FixtureFetcher {
+        FixtureFetcher::open(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/pages")).unwrap()
+    }
+
+    async fn crawl(url: &str, validators: &Validators) -> Result<CrawlOutcome, FetchError> {
+        crawl_page_with(&fixtures(), url, validators).await
+    }
+
+    #[tokio::test]
+    async fn parses_page_and_extracts_links() {
+        let Ok(CrawlOutcome::Fetched(res)) = crawl("https://fixture.test/", &Validators::default()).await else {
+            panic!("expected a fetched page");
+        };
+
+        assert_eq!(res.metadata.url, "https://fixture.test/");
+        assert_eq!(res.metadata.title.as_deref(), Some("Fixture Lab"));
+        assert_eq!(res.metadata.meta_description.as_deref(), Some("Recorded pages for crawler tests"));
+        assert_eq!(res.metadata.encoding.as_deref(), Some("UTF-8"));
+        assert!(res.metadata.cleaned_text.as_deref().unwrap().contains("We study crawlers"));
+
+        // query / fragment stripped, duplicates merged, nofollow and mailto dropped
+        let mut links = res.links.clone();
+        links.sort();
+        assert_eq!(
+            links,
+            vec![
+                "https://fixture.test/about",
+                "https://fixture.test/old-about",
+                "https://fixture.test/papers/1",
+                "https://other.test/x",
+            ]
+        );
+        assert_eq!(res.feeds, vec!["https://fixture.test/feed.xml"]);
+    }
+
+    #[tokio::test]
+    async fn follows_redirects() {
+        let Ok(CrawlOutcome::Fetched(res)) = crawl("https://fixture.test/old-about", &Validators::default()).await
+        else {
+            panic!("expected a fetched page");
+        };
+
+        assert_eq!(res.metadata.url, "https://fixture.test/about");
+        assert_eq!(res.metadata.redirects.len(), 1);
+        assert_eq!(res.metadata.redirects[0].status, 301);
+        assert_eq!(res.metadata.redirects[0].url, "https://fixture.test/old-about");
+        assert_eq!(res.links, vec!["https://fixture.test/"]);
+    }
+
+    #[tokio::test]
+    async fn matching_validators_are_not_modified() {
+        let validators = Validators {
+            etag: Some("\"about-v1\"".to_string()),
+            last_modified: None,
+        };
+        assert!(matches!(
+            crawl("https://fixture.test/about", &validators).await,
+            Ok(CrawlOutcome::NotModified)
+        ));
+
+        let stale = Validators {
+            etag: Some("\"about-v0\"".to_string()),
+            last_modified: None,
+        };
+        assert!(matches!(
+            crawl("https://fixture.test/about", &stale).await,
+            Ok(CrawlOutcome::Fetched(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn errors_are_classified() {
+        assert!(matches!(
+            crawl("https://fixture.test/missing", &Validators::default()).await,
+            Err(FetchError::ClientError(404))
+        ));
+        assert!(matches!(
+            crawl("https://fixture.test/logo.png", &Validators::default()).await,
+            Err(FetchError::NonHtml(_))
+        ));
+        assert!(matches!(
+            crawl("https://fixture.test/busy", &Validators::default()).await,
+            Err(FetchError::RateLimited { status: 429, .. })
+        ));
+        assert!(matches!(
+            crawl("https://fixture.test/guarded", &Validators::default()).await,
+            Err(FetchError::Protected("challenge"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn paper_pages_carry_their_extracted_metadata() {
+        // End-to-end through parse_page; each extractor has its own unit tests
+        let Ok(CrawlOutcome::Fetched(res)) = crawl("https://fixture.test/papers/2", &Validators::default()).await
+        else {
+            panic!("expected a fetched page");
+        };
+        assert!(res.metadata.is_protected);
+        assert_eq!(res.metadata.protection_reason, "paywall");
+        assert_eq!(res.metadata.directives, Default::default());
+        assert_eq!(res.metadata.scholarly.doi.as_deref(), Some("10.5555/fixture.2"));
+        assert_eq!(res.metadata.entities[0].types, vec!["ScholarlyArticle"]);
+    }
+}
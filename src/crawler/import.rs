@@ -0,0 +1,153 @@
+use chrono::DateTime;
+use r2d2_redis::redis::{RedisResult, cmd};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+use crate::crawler::clean_url;
+use crate::crawler::core::{ensure_bloom_filter, store_page};
+use crate::crawler::crawl::{CrawlOutcome, CrawlResult, parse_page};
+use crate::crawler::fetch::is_wanted_content_type;
+use crate::crawler::{canonical, soft404, utils};
+use crate::crawler::warc::{self, WarcReader};
+use crate::db::{get_kv_conn, paths};
+
+/// What happened to the response records of one file
+#[derive(Debug, Default)]
+struct ImportStats {
+    responses: usize,
+    out_of_scope: usize,
+    duplicates: usize,
+    unparsed: usize, // non-HTML, error statuses, parse failures
+    soft_404: usize,
+}
+
+/// `research import-warc <file or directory>...`
+///
+/// Seeds the index from existing WARC archives (e.g. Common Crawl segments) without fetching:
+/// every in-scope (DOMAINS_SET) HTML response goes through `parse_page` and the request-free
+/// post-fetch checks (soft-404 wording, known canonicals, document owner) and is stored like a
+/// crawled page, and its in-scope outlinks are pushed into the frontier.
+pub async fn run(args: &[String]) {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for arg in args {
+        let path = Path::new(arg);
+        if path.is_dir() {
+            match warc::list_files(path) {
+                Ok(found) => files.extend(found),
+                Err(err) => eprintln!("⚠️ Cannot read {}: {}", arg, err),
+            }
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+    if files.is_empty() {
+        eprintln!("❌ Usage: research import-warc <file.warc.gz | directory>...");
+        return;
+    }
+
+    ensure_bloom_filter(&mut get_kv_conn());
+
+    for path in files {
+        if let Err(err) = import_file(path.clone()).await {
+            eprintln!("⚠️ Import of {} failed: {}", path.display(), err);
+        }
+    }
+}
+
+async fn import_file(path: PathBuf) -> anyhow::Result<()> {
+    println!("📥 Importing {}", path.display());
+
+    // Reading and parsing is blocking work; storing (Quickwit, frontier) happens here
+    let (tx, mut rx) = mpsc::channel::<CrawlResult>(16);
+    let reader = tokio::task::spawn_blocking(move || read_pages(&path, tx));
+
+    let mut imported = 0;
+    while let Some(res) = rx.recv().await {
+        let url = res.metadata.url.clone();
+        match store_page(&url, res).await {
+            Ok(()) => {
+                // Only now may the live crawler skip the url
+                let mut kv_conn = get_kv_conn();
+                let _: RedisResult<()> = cmd("BF.ADD").arg(paths::CRAWL_SEEN).arg(utils::url_hash(&url)).query(&mut *kv_conn);
+                imported += 1;
+            }
+            Err(err) => eprintln!("⚠️ Failed to store {}: {}", url, err),
+        }
+    }
+
+    let stats = reader.await??;
+    println!(
+        "✅ Imported {} pages ({} responses, {} out of scope, {} duplicates, {} not parsed, {} soft-404)",
+        imported, stats.responses, stats.out_of_scope, stats.duplicates, stats.unparsed, stats.soft_404
+    );
+    Ok(())
+}
+
+fn read_pages(path: &Path, tx: mpsc::Sender<CrawlResult>) -> anyhow::Result<ImportStats> {
+    let mut stats = ImportStats::default();
+    let mut sent = HashSet::new(); // urls sent to be stored by this import
+
+    for record in WarcReader::open(path, 0)? {
+        let record = match record {
+            Ok(record) => record,
+            Err(err) => {
+                // Usually a truncated download: keep what was imported so far
+                eprintln!("⚠️ Stopped reading {}: {}", path.display(), err);
+                break;
+            }
+        };
+        let Some(response) = record.to_fetch_response() else { continue };
+        stats.responses += 1;
+
+        if !utils::in_scope(&response.url) {
+            stats.out_of_scope += 1;
+            continue;
+        }
+        let content_type = response.header("content-type").map(|ct| ct.to_lowercase());
+        if response.status != 200 || !is_wanted_content_type(content_type.as_deref()) {
+            stats.unparsed += 1;
+            continue;
+        }
+
+        // Already stored (crawled or imported before) or sent by this import. Not `crawl_seen`:
+        // that also holds urls merely queued, e.g. outlinks of pages imported a moment ago.
+        let url = clean_url(&response.url).unwrap_or_else(|| response.url.clone());
+        if sent.contains(&url) || utils::last_crawled_many(std::slice::from_ref(&url))[0].is_some() {
+            stats.duplicates += 1;
+            continue;
+        }
+
+        let Ok(CrawlOutcome::Fetched(mut res)) = parse_page(&response) else {
+            stats.unparsed += 1;
+            continue;
+        };
+        // No probing here, only the title / wording checks
+        if let Some(reason) = soft404::heuristic(&res.metadata) {
+            soft404::record(&url, reason);
+            stats.soft_404 += 1;
+            continue;
+        }
+        // Canonicals without a HEAD request, then one document per owner as in the live crawl
+        if let Some(variant) = canonical::consolidate_known(&mut res.metadata) {
+            canonical::record_alias(&variant, &res.metadata.url);
+        }
+        if !utils::claim_document(&res.metadata.url, &url) {
+            stats.duplicates += 1;
+            continue;
+        }
+
+        // Provenance: when the archive captured it, not when we read it
+        if let Some(captured_at) = record.field("warc-date").and_then(|d| DateTime::parse_from_rfc3339(d).ok()) {
+            res.metadata.crawl_timestamp = captured_at.timestamp();
+        }
+        res.links.retain(|link| utils::in_scope(link));
+
+        sent.insert(url);
+        if tx.blocking_send(*res).is_err() {
+            break;
+        }
+    }
+
+    Ok(stats)
+}
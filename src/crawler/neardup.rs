@@ -0,0 +1,180 @@
+use r2d2_redis::redis::{RedisResult, cmd, pipe};
+
+use crate::common::config::{NEAR_DUP_MAX_DISTANCE, SIMHASH_MIN_WORDS};
+use crate::crawler::crawl::PageMetadata;
+use crate::db::{get_kv_conn, paths};
+
+/// Words per shingle: near-duplicates share most 3-word sequences, unrelated pages few
+const SHINGLE_WORDS: usize = 3;
+
+/// 64-bit fingerprints are split into 8 bands of 8 bits. Two fingerprints within
+/// NEAR_DUP_MAX_DISTANCE (<= 6) bits of each other agree on at least two whole bands, so pages
+/// are bucketed by every pair of bands (28 buckets of 16 bits each) and only pages sharing a
+/// bucket have to be compared.
+const BANDS: u32 = 8;
+const BAND_BITS: u32 = 64 / BANDS;
+
+/// SimHash of `text` over word shingles, `None` when the text is too short to fingerprint reliably
+pub fn simhash(text: &str) -> Option<u64> {
+    simhash_min(text, SIMHASH_MIN_WORDS)
+}
+
+/// SimHash of any text with at least `min_words` words (and one shingle), e.g. short error pages
+pub fn simhash_min(text: &str, min_words: usize) -> Option<u64> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect();
+    if words.len() < min_words.max(SHINGLE_WORDS) {
+        return None;
+    }
+
+    let mut weights = [0i32; 64];
+    for shingle in words.windows(SHINGLE_WORDS) {
+        let hash = fnv1a(shingle);
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if hash & (1 << bit) != 0 {
+                *weight += 1;
+            } else {
+                *weight -= 1;
+            }
+        }
+    }
+
+    Some(
+        weights
+            .iter()
+            .enumerate()
+            .filter(|(_, weight)| **weight > 0)
+            .fold(0u64, |fp, (bit, _)| fp | (1 << bit)),
+    )
+}
+
+/// FNV-1a: stable across builds, unlike `DefaultHasher` (fingerprints are persisted)
+fn fnv1a(words: &[String]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in words.join(" ").bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn bucket_keys(fp: u64) -> Vec<String> {
+    let band = |i: u32| (fp >> (i * BAND_BITS)) & ((1 << BAND_BITS) - 1);
+    let mut keys = Vec::new();
+    for i in 0..BANDS {
+        for j in i + 1..BANDS {
+            keys.push(format!("{}:{}{}:{:02x}{:02x}", paths::SIMHASH_BANDS, i, j, band(i), band(j)));
+        }
+    }
+    keys
+}
+
+/// Fingerprints `metadata.cleaned_text` and puts the page into a near-duplicate cluster:
+/// the cluster of the closest known fingerprint within NEAR_DUP_MAX_DISTANCE bits, or a new
+/// cluster represented by this page. Sets `simhash` and `cluster_id`; returns whether the page
+/// is its cluster's representative.
+pub fn assign_cluster(metadata: &mut PageMetadata) -> bool {
+    let Some(fp) = metadata.cleaned_text.as_deref().and_then(simhash) else {
+        return true;
+    };
+    metadata.simhash = Some(fp);
+
+    let mut kv_conn = get_kv_conn();
+    let mut lookup = pipe();
+    for key in bucket_keys(fp) {
+        lookup.cmd("SMEMBERS").arg(key);
+    }
+    let buckets: Vec<Vec<String>> = lookup.query(&mut *kv_conn).unwrap_or_default();
+
+    let closest = buckets
+        .iter()
+        .flatten()
+        .filter_map(|member| u64::from_str_radix(member, 16).ok())
+        .map(|candidate| (candidate, (candidate ^ fp).count_ones()))
+        .filter(|(_, distance)| *distance <= NEAR_DUP_MAX_DISTANCE)
+        .min_by_key(|(_, distance)| *distance);
+
+    let cluster: Option<String> = closest.and_then(|(candidate, _)| {
+        cmd("HGET")
+            .arg(paths::SIMHASH_CLUSTER)
+            .arg(format!("{:016x}", candidate))
+            .query(&mut *kv_conn)
+            .unwrap_or(None)
+    });
+    let cluster_id = cluster.unwrap_or_else(|| metadata.url.clone());
+
+    // Register this fingerprint too, so later variants can match either copy
+    let member = format!("{:016x}", fp);
+    let mut register = pipe();
+    for key in bucket_keys(fp) {
+        register.cmd("SADD").arg(key).arg(&member).ignore();
+    }
+    register.cmd("HSETNX").arg(paths::SIMHASH_CLUSTER).arg(&member).arg(&cluster_id).ignore();
+    let _: RedisResult<()> = register.query(&mut *kv_conn);
+
+    let representative = cluster_id == metadata.url;
+    if !representative {
+        println!("👯 Near-duplicate: {} ~ {}", metadata.url, cluster_id);
+    }
+    metadata.cluster_id = Some(cluster_id);
+    representative
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "Researchers measured the thermal conductivity of layered graphite samples \
+        grown under varying pressure and compared the results with density functional predictions. \
+        The measurements agree with theory above one hundred kelvin, while at lower temperatures \
+        boundary scattering dominates and the conductivity falls faster than expected. Samples \
+        grown at the highest pressure showed the fewest stacking faults and the longest phonon \
+        mean free paths. We discuss implications for heat spreaders in compact electronics and \
+        outline further experiments on isotopically purified crystals.";
+
+    #[test]
+    fn identical_texts_share_a_fingerprint() {
+        let fp = simhash(TEXT).unwrap();
+        assert_eq!(simhash(TEXT), Some(fp));
+        // Case and punctuation are not part of the shingles
+        assert_eq!(simhash(&TEXT.to_uppercase().replace(['.', ','], " ")), Some(fp));
+    }
+
+    #[test]
+    fn near_variants_are_close_and_unrelated_texts_are_not() {
+        let fp = simhash(TEXT).unwrap();
+        let variant = simhash(&TEXT.replace("compact electronics", "small devices")).unwrap();
+        assert!((fp ^ variant).count_ones() <= NEAR_DUP_MAX_DISTANCE);
+
+        let unrelated = "The city council approved a new budget for road repairs and public libraries \
+            after a long debate about property taxes, school funding and the future of the harbour. \
+            Residents who attended the meeting asked for more bicycle lanes, longer opening hours at \
+            the swimming pool and a plan to replace the old bridge before winter storms arrive again. \
+            The mayor promised a report by spring.";
+        let other = simhash(unrelated).unwrap();
+        assert!((fp ^ other).count_ones() > NEAR_DUP_MAX_DISTANCE);
+    }
+
+    #[test]
+    fn short_texts_are_not_fingerprinted() {
+        assert_eq!(simhash("Page not found. The page you requested does not exist."), None);
+        assert!(simhash_min("Page not found. The page you requested does not exist.", 5).is_some());
+        assert_eq!(simhash_min("two words", 1), None); // less than one shingle
+    }
+
+    #[test]
+    fn fingerprints_within_the_distance_share_a_bucket() {
+        let fp = simhash(TEXT).unwrap();
+        let keys = bucket_keys(fp);
+        assert_eq!(keys.len(), 28);
+        assert!(keys.iter().all(|key| key.starts_with(paths::SIMHASH_BANDS)));
+
+        // Flip NEAR_DUP_MAX_DISTANCE bits spread over as many bands as possible
+        let near = (0..NEAR_DUP_MAX_DISTANCE).fold(fp, |fp, i| fp ^ (1 << (i * BAND_BITS + i)));
+        assert!(bucket_keys(near).iter().any(|key| keys.contains(key)));
+        assert!(!bucket_keys(!fp).iter().any(|key| keys.contains(key)));
+    }
+}
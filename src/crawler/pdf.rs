@@ -0,0 +1,130 @@
+use lopdf::{Document, Object};
+use std::panic;
+
+use crate::crawler::crawl::{CrawlResult, MAX_TEXT_CHARS, PageMetadata};
+use crate::crawler::fetch::FetchError;
+
+/// Extracts text and document-info title / author from a PDF body.
+///
+/// `metadata` arrives with the HTTP fields (url, headers, timestamp) already filled in.
+pub fn parse_pdf(body: &[u8], mut metadata: PageMetadata) -> Result<CrawlResult, FetchError> {
+    if !body.starts_with(b"%PDF-") {
+        return Err(FetchError::Other("pdf: missing %PDF header".to_string()));
+    }
+
+    // pdf-extract panics on plenty of real-world files instead of returning errors
+    let text = panic::catch_unwind(|| pdf_extract::extract_text_from_mem(body))
+        .map_err(|_| FetchError::Other("pdf: extractor panicked".to_string()))?
+        .map_err(|err| FetchError::Other(format!("pdf: {}", err)))?;
+
+    let cleaned: String = text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(MAX_TEXT_CHARS)
+        .collect();
+
+    if cleaned.is_empty() {
+        // scanned paper without a text layer: nothing to search
+        return Err(FetchError::Other("pdf: no text layer".to_string()));
+    }
+
+    let (info_title, info_author) = Document::load_mem(body)
+        .map(|doc| (info_string(&doc, b"Title"), info_string(&doc, b"Author")))
+        .unwrap_or((None, None));
+
+    // Many generators leave Title empty or set it to the file name -> fall back to the first line
+    let title = info_title
+        .filter(|t| !t.to_lowercase().ends_with(".pdf"))
+        .or_else(|| {
+            text.lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty())
+                .map(|line| line.chars().take(200).collect())
+        });
+
+    metadata.title = title;
+    metadata.author = info_author;
+    metadata.cleaned_text = Some(cleaned);
+
+    Ok(CrawlResult {
+        metadata,
+        links: Vec::new(),
+        feeds: Vec::new(),
+    })
+}
+
+/// Reads a text entry from the trailer's /Info dictionary
+fn info_string(doc: &Document, key: &[u8]) -> Option<String> {
+    let info = doc.trailer.get(b"Info").ok()?;
+    let info = match info.as_reference() {
+        Ok(id) => doc.get_object(id).ok()?,
+        Err(_) => info,
+    };
+
+    match info.as_dict().ok()?.get(key).ok()? {
+        Object::String(bytes, _) => {
+            let value = decode_pdf_text(bytes);
+            let value = value.trim();
+            (!value.is_empty()).then(|| value.to_string())
+        }
+        _ => None,
+    }
+}
+
+/// PDF text strings are UTF-16BE with a BOM, otherwise PDFDocEncoding (close enough to Latin-1)
+fn decode_pdf_text(bytes: &[u8]) -> String {
+    if let Some(utf16) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        let units: Vec<u16> = utf16
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        return String::from_utf16_lossy(&units);
+    }
+    if let Some(utf8) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        return String::from_utf8_lossy(utf8).to_string();
+    }
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The PDF body of the recorded `papers/2.pdf` response
+    fn fixture_pdf() -> Vec<u8> {
+        let raw = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/pages/paper-pdf.http")).unwrap();
+        let body = raw.windows(2).position(|w| w == b"\n\n").unwrap() + 2;
+        raw[body..].to_vec()
+    }
+
+    #[test]
+    fn text_and_document_info_are_extracted() {
+        let res = parse_pdf(&fixture_pdf(), PageMetadata::default()).unwrap();
+        // The /Title is just the file name, so the first line stands in for it
+        assert_eq!(res.metadata.title.as_deref(), Some("Crawling at Scale"));
+        assert_eq!(res.metadata.author.as_deref(), Some("Ada Lovelace"));
+        let text = res.metadata.cleaned_text.unwrap();
+        assert!(text.contains("Polite crawlers revisit pages on a schedule."), "{}", text);
+        assert!(res.links.is_empty());
+    }
+
+    #[test]
+    fn broken_pdfs_are_errors() {
+        assert!(matches!(parse_pdf(b"<html></html>", PageMetadata::default()), Err(FetchError::Other(_))));
+        assert!(matches!(
+            parse_pdf(b"%PDF-1.4\nnot really a pdf", PageMetadata::default()),
+            Err(FetchError::Other(_))
+        ));
+        let truncated = fixture_pdf()[..200].to_vec();
+        assert!(parse_pdf(&truncated, PageMetadata::default()).is_err());
+    }
+
+    #[test]
+    fn info_strings_are_decoded() {
+        assert_eq!(decode_pdf_text(&[0xFE, 0xFF, 0x00, 0x41, 0x00, 0xE9]), "Aé");
+        assert_eq!(decode_pdf_text(&[0xEF, 0xBB, 0xBF, 0xC3, 0xA9]), "é");
+        assert_eq!(decode_pdf_text(b"Caf\xe9"), "Café");
+    }
+}
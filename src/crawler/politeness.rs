@@ -0,0 +1,151 @@
+use chrono::{DateTime, Utc};
+use r2d2_redis::redis::{RedisResult, cmd};
+use std::time::Duration;
+
+use crate::common::config::{
+    DEFAULT_RETRY_AFTER_SECS, HOST_DELAY_MS, MAX_HOST_DELAY_MS, MAX_RETRY_AFTER_SECS,
+    CRAWLER_TOKEN,
+};
+use crate::crawler::robots::RobotsRules;
+use crate::db::{get_kv_conn, paths};
+
+/// Outcome of asking for a request slot on a host
+pub enum Slot {
+    Ready,
+    Wait(u64), // ms until the host's cooldown expires
+}
+
+/// Minimum gap between two requests to a host: our default, raised by robots.txt `Crawl-delay`
+pub fn host_delay_ms(rules: &RobotsRules) -> u64 {
+    let robots_delay = rules
+        .crawl_delay(&CRAWLER_TOKEN)
+        .map(|secs| (secs * 1000.0) as u64)
+        .unwrap_or(0)
+        .min(MAX_HOST_DELAY_MS);
+
+    (*HOST_DELAY_MS).max(robots_delay)
+}
+
+/// Reserves the next request slot for `host`.
+///
+/// The cooldown key lives in kvrocks (`SET NX PX`), so the slot is shared by every
+/// crawler task and process: whoever creates the key may fetch, everyone else waits
+/// for it to expire.
+pub fn try_acquire(host: &str, delay_ms: u64) -> Slot {
+    let key = format!("{}:{}", paths::HOST_COOLDOWN, host);
+    let mut conn = get_kv_conn();
+
+    let acquired: Option<String> = cmd("SET")
+        .arg(&key)
+        .arg(1)
+        .arg("NX")
+        .arg("PX")
+        .arg(delay_ms.max(1))
+        .query(&mut *conn)
+        .unwrap_or(None);
+
+    if acquired.is_some() {
+        return Slot::Ready;
+    }
+
+    let pttl: i64 = cmd("PTTL").arg(&key).query(&mut *conn).unwrap_or(delay_ms as i64);
+    Slot::Wait(pttl.max(1) as u64)
+}
+
+/// Waits (asynchronously) until a request slot on `host` is ours; for side fetches like sitemaps
+pub async fn wait_for_slot(host: &str, delay_ms: u64) {
+    while let Slot::Wait(wait_ms) = try_acquire(host, delay_ms) {
+        tokio::time::sleep(Duration::from_millis(wait_ms)).await;
+    }
+}
+
+/// Parks `host` for `wait` (429 / 503 with Retry-After), overriding the normal cooldown
+pub fn back_off(host: &str, wait: Duration) {
+    let key = format!("{}:{}", paths::HOST_COOLDOWN, host);
+    let wait_ms = wait.as_millis().min(MAX_RETRY_AFTER_SECS as u128 * 1000) as u64;
+    let mut conn = get_kv_conn();
+
+    // Only extend: never shorten a longer cooldown someone else already set
+    let pttl: i64 = cmd("PTTL").arg(&key).query(&mut *conn).unwrap_or(0);
+    if pttl >= wait_ms as i64 {
+        return;
+    }
+
+    let _: RedisResult<()> = cmd("SET")
+        .arg(&key)
+        .arg(1)
+        .arg("PX")
+        .arg(wait_ms.max(1))
+        .query(&mut *conn);
+
+    println!("🐢 Backing off {} for {}s", host, wait_ms / 1000);
+}
+
+/// Parses a `Retry-After` value (delta-seconds or HTTP-date); falls back to the default wait
+pub fn parse_retry_after(value: Option<&str>) -> Duration {
+    let secs = value
+        .map(str::trim)
+        .and_then(|v| {
+            v.parse::<u64>().ok().or_else(|| {
+                DateTime::parse_from_rfc2822(v)
+                    .ok()
+                    .map(|at| (at.with_timezone(&Utc) - Utc::now()).num_seconds().max(0) as u64)
+            })
+        })
+        .unwrap_or(DEFAULT_RETRY_AFTER_SECS);
+
+    Duration::from_secs(secs.min(MAX_RETRY_AFTER_SECS))
+}
+
+/// Puts `url` aside until its host is ready again
+pub fn defer(url: &str, wait_ms: u64) {
+    let ready_at = Utc::now().timestamp_millis() + wait_ms as i64;
+    let mut conn = get_kv_conn();
+    let _: RedisResult<()> = cmd("ZADD")
+        .arg(paths::DEFERRED)
+        .arg(ready_at)
+        .arg(url)
+        .query(&mut *conn);
+}
+
+/// Claims one deferred url whose host cooldown has passed
+pub fn pop_ready() -> Option<String> {
+    let now = Utc::now().timestamp_millis();
+    let mut conn = get_kv_conn();
+
+    let ready: Vec<String> = cmd("ZRANGEBYSCORE")
+        .arg(paths::DEFERRED)
+        .arg("-inf")
+        .arg(now)
+        .arg("LIMIT")
+        .arg(0)
+        .arg(1)
+        .query(&mut *conn)
+        .unwrap_or_default();
+
+    let url = ready.into_iter().next()?;
+
+    // ZREM returns 1 only for the task that actually removed it
+    let claimed: i64 = cmd("ZREM")
+        .arg(paths::DEFERRED)
+        .arg(&url)
+        .query(&mut *conn)
+        .unwrap_or(0);
+
+    (claimed == 1).then_some(url)
+}
+
+/// Milliseconds until the next deferred url becomes ready, `None` when nothing is deferred
+pub fn next_ready_in() -> Option<u64> {
+    let mut conn = get_kv_conn();
+    let next: Vec<(String, f64)> = cmd("ZRANGE")
+        .arg(paths::DEFERRED)
+        .arg(0)
+        .arg(0)
+        .arg("WITHSCORES")
+        .query(&mut *conn)
+        .unwrap_or_default();
+
+    next.first()
+        .map(|(_, ready_at)| (*ready_at as i64 - Utc::now().timestamp_millis()).max(0) as u64)
+}
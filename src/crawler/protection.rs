@@ -0,0 +1,282 @@
+use r2d2_redis::redis::{RedisResult, pipe};
+use reqwest::header::HeaderMap;
+use scraper::{ElementRef, Html, Selector};
+use url::Url;
+
+use crate::crawler::fetch::RedirectHop;
+use crate::db::{get_kv_conn, paths};
+
+/// Markup only anti-bot interstitials carry (Cloudflare, PerimeterX, Incapsula): script urls,
+/// script variables and form ids, never article prose
+const CHALLENGE_MARKUP: [&str; 6] = [
+    "window._cf_chl_opt",
+    "/cdn-cgi/challenge-platform/h/",
+    "id=\"challenge-form\"",
+    "class=\"cf-browser-verification",
+    "id=\"px-captcha\"",
+    "/_incapsula_resource?",
+];
+
+/// Interstitial titles (Cloudflare, Sucuri, DDoS-Guard, Imperva)
+const CHALLENGE_TITLES: [&str; 7] = [
+    "just a moment...",
+    "attention required! | cloudflare",
+    "checking your browser",
+    "verify you are human",
+    "sucuri website firewall",
+    "ddos-guard",
+    "pardon our interruption",
+];
+
+/// Interstitial wording; only trusted on 401 / 403 / 503 responses, 200 pages may be articles about it
+const CHALLENGE_TEXTS: [&str; 4] = [
+    "checking your browser before accessing",
+    "verify you are human",
+    "sucuri website firewall",
+    "ddos-guard",
+];
+
+/// Publisher "buy / log in to read" blocks
+const PAYWALL_MARKERS: [&str; 9] = [
+    "access this article",
+    "buy article pdf",
+    "purchase pdf",
+    "get access to the full version of this article",
+    "log in via an institution",
+    "subscribe to read",
+    "this content is only available to subscribers",
+    "class=\"paywall",
+    "id=\"paywall",
+];
+
+/// Titles of block pages that replace the content entirely (may carry a site suffix)
+const DENIED_TITLES: [&str; 4] = ["access denied", "403 forbidden", "you have been blocked", "request rejected"];
+
+const LOGIN_PATHS: [&str; 8] = ["/login", "/signin", "/sign-in", "/sso", "/auth/", "/idp/", "/shibboleth", "/cas/"];
+
+/// Pages this short with a password field are login forms, not articles
+const LOGIN_FORM_MAX_TEXT: usize = 1500;
+/// ...unless they have more than this many real paragraphs outside the form
+const LOGIN_FORM_MAX_PARAGRAPHS: usize = 2;
+
+/// Where a password field is a side widget, not the page (class / id fragments)
+const LOGIN_WIDGET_HINTS: [&str; 6] = ["sidebar", "widget", "dropdown", "modal", "popup", "menu"];
+
+/// Error statuses that are really a protection page: `Some("challenge" | "login" | "denied")`
+pub fn detect_blocked_status(status: u16, headers: &HeaderMap, body: &[u8]) -> Option<&'static str> {
+    if !matches!(status, 401 | 403 | 503) {
+        return None;
+    }
+    let html = String::from_utf8_lossy(&body[..body.len().min(64 * 1024)]).to_lowercase();
+    let title = html
+        .split_once("<title>")
+        .and_then(|(_, rest)| rest.split_once("</title>"))
+        .map_or("", |(title, _)| title);
+    if is_challenge(headers, &html, title) || CHALLENGE_TEXTS.iter().any(|text| html.contains(text)) {
+        return Some("challenge");
+    }
+    match status {
+        401 => Some("login"),
+        403 => Some("denied"),
+        _ => None, // a plain 503 is an outage, not a block
+    }
+}
+
+/// Classifies a 200 HTML page. `challenge`, `login` and `denied` mean the content is not the page;
+/// `paywall` means we got the landing page (abstract, metadata) but not the full text.
+pub fn detect(
+    url: &str,
+    headers: &HeaderMap,
+    redirects: &[RedirectHop],
+    document: &Html,
+    html: &str,
+    title: Option<&str>,
+    text_len: usize,
+) -> Option<&'static str> {
+    let html = html.to_lowercase();
+    let title = title.unwrap_or_default().trim().to_lowercase();
+
+    if is_challenge(headers, &html, &title) {
+        return Some("challenge");
+    }
+
+    if title == "forbidden" || DENIED_TITLES.iter().any(|denied| title.starts_with(denied)) {
+        return Some("denied");
+    }
+
+    // Redirected to a sign-in page, or the page is little more than a password form
+    let redirected_to_login = !redirects.is_empty() && is_login_url(url);
+    if redirected_to_login || (text_len < LOGIN_FORM_MAX_TEXT && is_login_form_page(document)) {
+        return Some("login");
+    }
+
+    if is_not_free(&html) || PAYWALL_MARKERS.iter().any(|marker| html.contains(marker)) {
+        return Some("paywall");
+    }
+
+    None
+}
+
+/// `cf-mitigated: challenge`, an interstitial title or interstitial markup (lowercased html / title)
+fn is_challenge(headers: &HeaderMap, html: &str, title: &str) -> bool {
+    let mitigated = headers
+        .get("cf-mitigated")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("challenge"));
+    mitigated
+        || CHALLENGE_TITLES.iter().any(|challenge| title.trim().starts_with(challenge))
+        || CHALLENGE_MARKUP.iter().any(|marker| html.contains(marker))
+}
+
+/// A password form is the page's main content: not a sidebar / header / menu widget, and next to
+/// it at most LOGIN_FORM_MAX_PARAGRAPHS paragraphs of real text
+fn is_login_form_page(document: &Html) -> bool {
+    let inputs = Selector::parse("input[type]").unwrap();
+    let dominant_form = document
+        .select(&inputs)
+        .filter(|input| input.attr("type").is_some_and(|t| t.eq_ignore_ascii_case("password")))
+        .any(|input| !in_side_widget(input));
+    if !dominant_form {
+        return false;
+    }
+
+    let paragraphs = document
+        .select(&Selector::parse("p").unwrap())
+        .filter(|p| !p.ancestors().filter_map(ElementRef::wrap).any(|a| a.value().name() == "form"))
+        .filter(|p| p.text().collect::<String>().trim().chars().count() >= 80)
+        .count();
+    paragraphs <= LOGIN_FORM_MAX_PARAGRAPHS
+}
+
+fn in_side_widget(element: ElementRef) -> bool {
+    element.ancestors().filter_map(ElementRef::wrap).any(|ancestor| {
+        let el = ancestor.value();
+        let hints = format!("{} {}", el.attr("class").unwrap_or_default(), el.attr("id").unwrap_or_default()).to_lowercase();
+        matches!(el.name(), "aside" | "nav" | "header" | "footer")
+            || LOGIN_WIDGET_HINTS.iter().any(|hint| hints.contains(hint))
+    })
+}
+
+fn is_login_url(url: &str) -> bool {
+    let Ok(parsed) = Url::parse(url) else { return false };
+    let path = parsed.path().to_lowercase();
+    let host = parsed.host_str().unwrap_or_default();
+    host.starts_with("login.") || host.starts_with("auth.") || LOGIN_PATHS.iter().any(|p| path.contains(p))
+}
+
+/// schema.org `"isAccessibleForFree": false` (JSON-LD), the publishers' own paywall flag
+fn is_not_free(html: &str) -> bool {
+    let mut rest = html;
+    while let Some(pos) = rest.find("isaccessibleforfree") {
+        rest = &rest[pos + "isaccessibleforfree".len()..];
+        let value: String = rest
+            .chars()
+            .take(20)
+            .filter(|c| c.is_ascii_alphanumeric())
+            .collect();
+        if value.starts_with("false") || value.starts_with("contentfalse") {
+            return true;
+        }
+    }
+    false
+}
+
+/// Counts a fetched page for its domain's protection rate (`prot:<domain>` hash: pages + one field per reason)
+pub fn record(url: &str, reason: Option<&str>) {
+    let Some(domain) = Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h.trim_start_matches("www.").to_string()))
+    else {
+        return;
+    };
+
+    let key = format!("{}:{}", paths::PROTECTION, domain);
+    let mut pipeline = pipe();
+    pipeline.cmd("HINCRBY").arg(&key).arg("pages").arg(1).ignore();
+    if let Some(reason) = reason {
+        pipeline.cmd("HINCRBY").arg(&key).arg(reason).arg(1).ignore();
+        pipeline.cmd("HINCRBY").arg(&key).arg("protected").arg(1).ignore();
+    }
+
+    let mut kv_conn = get_kv_conn();
+    let _: RedisResult<()> = pipeline.query(&mut *kv_conn);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn classify(html: &str, headers: &HeaderMap) -> Option<&'static str> {
+        let document = Html::parse_document(html);
+        let title = document
+            .select(&Selector::parse("title").unwrap())
+            .next()
+            .map(|t| t.text().collect::<String>());
+        let text_len = document.root_element().text().map(|t| t.trim().chars().count()).sum();
+        detect("https://example.org/a", headers, &[], &document, html, title.as_deref(), text_len)
+    }
+
+    #[test]
+    fn challenges_are_recognised_by_title_markup_and_headers() {
+        let none = HeaderMap::new();
+        assert_eq!(classify("<title>Just a moment...</title><body>…</body>", &none), Some("challenge"));
+        assert_eq!(
+            classify("<title>x</title><script>window._cf_chl_opt = {};</script>", &none),
+            Some("challenge")
+        );
+
+        let mut mitigated = HeaderMap::new();
+        mitigated.insert("cf-mitigated", HeaderValue::from_static("challenge"));
+        assert_eq!(classify("<title>Home</title>", &mitigated), Some("challenge"));
+    }
+
+    #[test]
+    fn articles_about_challenges_are_not_challenges() {
+        let article = "<title>Why bots see DDoS-Guard pages</title><body><article>\
+            <p>Sites behind ddos-guard or Cloudflare ask visitors to verify you are human before \
+            showing anything, and the page says it is checking your browser before accessing the site.</p>\
+            </article></body>";
+        assert_eq!(classify(article, &HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn blocked_statuses_use_the_interstitial_wording() {
+        let none = HeaderMap::new();
+        assert_eq!(
+            detect_blocked_status(503, &none, b"<p>Checking your browser before accessing example.org</p>"),
+            Some("challenge")
+        );
+        assert_eq!(detect_blocked_status(503, &none, b"<p>Down for maintenance</p>"), None);
+        assert_eq!(detect_blocked_status(403, &none, b"<p>Nope</p>"), Some("denied"));
+        assert_eq!(detect_blocked_status(404, &none, b"<p>verify you are human</p>"), None);
+    }
+
+    #[test]
+    fn login_walls_need_a_dominant_password_form() {
+        let none = HeaderMap::new();
+        let login = "<title>Sign in</title><body><main><form><input name=user>\
+            <input type=\"password\" name=pass><button>Sign in</button></form></main></body>";
+        assert_eq!(classify(login, &none), Some("login"));
+
+        let sidebar = "<title>News</title><body><main><p>Short news item.</p></main>\
+            <aside><form><input name=user><input type=\"password\"></form></aside></body>";
+        assert_eq!(classify(sidebar, &none), None);
+
+        let paragraph = "<p>This page has a search form, but it is an article with several real paragraphs of text in it.</p>";
+        let article = format!(
+            "<title>Article</title><body><form><input type=password></form>{}</body>",
+            paragraph.repeat(3)
+        );
+        assert_eq!(classify(&article, &none), None);
+    }
+
+    #[test]
+    fn paywalls_are_flagged_not_blocked() {
+        let none = HeaderMap::new();
+        let landing = r#"<title>Paper</title><script type="application/ld+json">{"isAccessibleForFree": false}</script>"#;
+        assert_eq!(classify(landing, &none), Some("paywall"));
+        assert_eq!(classify("<title>Paper</title><a>Buy article PDF</a>", &none), Some("paywall"));
+        assert_eq!(classify("<title>Paper</title><p>Open access.</p>", &none), None);
+    }
+}
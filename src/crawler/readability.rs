@@ -0,0 +1,383 @@
+use scraper::{ElementRef, Html, Node};
+
+use crate::crawler::crawl::MAX_TEXT_CHARS;
+
+/// Never content: scripts, chrome, forms and embedded media
+const SKIP_TAGS: [&str; 21] = [
+    "head", "script", "style", "noscript", "template", "iframe", "object", "embed", "canvas", "svg", "math",
+    "nav", "footer", "aside", "menu", "dialog", "form", "button", "input", "select", "textarea",
+];
+
+/// Elements that start a new block of text. Every block keeps only the text that is not inside
+/// a nested block, so nested containers never repeat their children's text.
+const BLOCK_TAGS: [&str; 33] = [
+    "html", "body", "main", "article", "section", "div", "header", "p", "blockquote", "pre", "address",
+    "h1", "h2", "h3", "h4", "h5", "h6", "ul", "ol", "li", "dl", "dt", "dd", "table", "caption", "thead",
+    "tbody", "tr", "td", "th", "figure", "figcaption", "center",
+];
+
+const HEADINGS: [&str; 6] = ["h1", "h2", "h3", "h4", "h5", "h6"];
+
+/// class / id fragments of page furniture; such subtrees are dropped unless they also look like content
+const UNLIKELY: [&str; 27] = [
+    "banner", "breadcrumb", "combx", "comment", "community", "cookie", "consent", "disqus", "gdpr", "menu",
+    "navbar", "related", "remark", "replies", "rss", "share", "shoutbox", "sidebar", "skyscraper", "social",
+    "sponsor", "ad-break", "agegate", "pagination", "pager", "popup", "newsletter",
+];
+const MAYBE: [&str; 7] = ["and", "article", "body", "column", "content", "main", "abstract"];
+
+/// class / id fragments that raise or lower a container's score
+const POSITIVE: [&str; 12] = [
+    "article", "body", "content", "entry", "hentry", "main", "page", "post", "text", "blog", "story", "abstract",
+];
+const NEGATIVE: [&str; 17] = [
+    "hidden", "banner", "combx", "comment", "contact", "foot", "footer", "footnote", "masthead", "meta", "promo",
+    "related", "scroll", "shoutbox", "sidebar", "sponsor", "widget",
+];
+
+/// Blocks shorter than this don't vote for their container
+const MIN_PARAGRAPH_CHARS: usize = 25;
+/// Blocks that are mostly link text (menus, "related" lists, tag clouds) are left out
+const MAX_LINK_DENSITY: f64 = 0.5;
+
+#[derive(Debug)]
+struct Block {
+    parent: Option<usize>, // nearest enclosing block
+    tag: String,
+    class_weight: f64,
+    text: String,       // text directly in this block (not in nested blocks)
+    link_chars: usize,  // part of `text` inside <a>
+    total_chars: usize, // text of the whole subtree
+    total_link_chars: usize,
+    score: Option<f64>, // set once a paragraph votes for it
+    votes: f64,           // score without the tag / class bonus
+    paragraph_votes: f64, // votes from its own paragraphs only (no grandchildren)
+}
+
+impl Block {
+    fn link_density(&self) -> f64 {
+        if self.total_chars == 0 { 0.0 } else { self.total_link_chars as f64 / self.total_chars as f64 }
+    }
+}
+
+/// Main-content text of a page, readability style: the container whose paragraphs score highest
+/// (text length, commas, little link text) plus its high-scoring siblings. Paragraphs are kept
+/// as separate lines separated by a blank line.
+pub fn extract(document: &Html) -> String {
+    let mut blocks: Vec<Block> = Vec::new();
+    collect(document.root_element(), None, false, &mut blocks);
+    for block in &mut blocks {
+        block.text = if block.tag == "pre" {
+            // Code and preformatted text keep their lines
+            block
+                .text
+                .lines()
+                .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n")
+        } else {
+            block.text.split_whitespace().collect::<Vec<_>>().join(" ")
+        };
+    }
+
+    // Subtree totals; children always come after their parent
+    for i in (0..blocks.len()).rev() {
+        blocks[i].total_chars += blocks[i].text.chars().count();
+        blocks[i].total_link_chars += blocks[i].link_chars;
+        if let Some(parent) = blocks[i].parent {
+            blocks[parent].total_chars += blocks[i].total_chars;
+            blocks[parent].total_link_chars += blocks[i].total_link_chars;
+        }
+    }
+
+    score_paragraphs(&mut blocks);
+
+    let top = top_candidate(&blocks);
+
+    let keep: Vec<bool> = match top {
+        Some((top, top_score)) => {
+            let accepted = accepted_nodes(&blocks, top, top_score);
+            (0..blocks.len())
+                .map(|i| {
+                    let mut node = Some(i);
+                    while let Some(n) = node {
+                        if accepted.contains(&n) {
+                            return true;
+                        }
+                        node = blocks[n].parent;
+                    }
+                    false
+                })
+                .collect()
+        }
+        None => vec![true; blocks.len()], // nothing paragraph-like: keep whatever isn't a link list
+    };
+
+    let mut paragraphs: Vec<&str> = Vec::new();
+    let mut chars = 0;
+    for (block, keep) in blocks.iter().zip(keep) {
+        if !keep || block.text.is_empty() {
+            continue;
+        }
+        let own_chars = block.text.chars().count();
+        if block.link_chars as f64 / own_chars as f64 > MAX_LINK_DENSITY && !HEADINGS.contains(&block.tag.as_str()) {
+            continue;
+        }
+        if paragraphs.last() == Some(&block.text.as_str()) {
+            continue;
+        }
+        paragraphs.push(&block.text);
+        chars += own_chars + 2;
+        if chars >= MAX_TEXT_CHARS {
+            break;
+        }
+    }
+
+    paragraphs.join("\n\n").chars().take(MAX_TEXT_CHARS).collect()
+}
+
+/// Walks the DOM, opening a block for every block element and appending text to the innermost one
+fn collect(element: ElementRef, parent: Option<usize>, in_link: bool, blocks: &mut Vec<Block>) {
+    let el = element.value();
+    let tag = el.name();
+    if SKIP_TAGS.contains(&tag) || is_hidden(element) || is_unlikely(element) {
+        return;
+    }
+    // A page's masthead is chrome, an article's own <header> (title, byline) is not
+    if tag == "header" && !has_ancestor(element, &["article", "main"]) {
+        return;
+    }
+
+    let current = if BLOCK_TAGS.contains(&tag) || parent.is_none() {
+        blocks.push(Block {
+            parent,
+            tag: tag.to_string(),
+            class_weight: class_weight(element),
+            text: String::new(),
+            link_chars: 0,
+            total_chars: 0,
+            total_link_chars: 0,
+            score: None,
+            votes: 0.0,
+            paragraph_votes: 0.0,
+        });
+        Some(blocks.len() - 1)
+    } else {
+        parent
+    };
+    let in_link = in_link || tag == "a";
+
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => {
+                if let Some(current) = current {
+                    let block = &mut blocks[current];
+                    block.text.push_str(text);
+                    if in_link {
+                        block.link_chars += text.split_whitespace().map(|w| w.chars().count() + 1).sum::<usize>();
+                    }
+                }
+            }
+            Node::Element(child_el) if child_el.name() == "br" => {
+                if let Some(current) = current {
+                    blocks[current].text.push(' ');
+                }
+            }
+            Node::Element(_) => {
+                if let Some(child) = ElementRef::wrap(child) {
+                    collect(child, current, in_link, blocks);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Every paragraph-sized block votes for its container (fully) and the container's container (half)
+fn score_paragraphs(blocks: &mut [Block]) {
+    for i in 0..blocks.len() {
+        let len = blocks[i].text.chars().count();
+        if len < MIN_PARAGRAPH_CHARS || HEADINGS.contains(&blocks[i].tag.as_str()) {
+            continue;
+        }
+        let commas = blocks[i].text.matches([',', '，']).count();
+        let vote = 1.0 + commas as f64 + (len as f64 / 100.0).min(3.0);
+
+        let Some(parent) = blocks[i].parent else { continue };
+        add_score(blocks, parent, vote);
+        blocks[parent].paragraph_votes += vote;
+        if let Some(grandparent) = blocks[parent].parent {
+            add_score(blocks, grandparent, vote / 2.0);
+        }
+    }
+}
+
+fn add_score(blocks: &mut [Block], i: usize, vote: f64) {
+    let block = &mut blocks[i];
+    let base = match block.tag.as_str() {
+        "article" | "main" => 10.0,
+        "div" | "section" => 5.0,
+        "pre" | "td" | "blockquote" => 3.0,
+        "ul" | "ol" | "dl" | "dd" | "dt" | "li" | "address" => -3.0,
+        "th" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => -5.0,
+        _ => 0.0,
+    };
+    *block.score.get_or_insert(base + block.class_weight) += vote;
+    block.votes += vote;
+}
+
+/// Container score after the link density penalty
+fn adjusted_score(block: &Block) -> Option<f64> {
+    block.score.map(|score| score * (1.0 - block.link_density()))
+}
+
+/// The best-scoring container. When other containers hold comparable paragraphs (an article
+/// split into several <section>s), the closest ancestor holding them as well is used instead.
+fn top_candidate(blocks: &[Block]) -> Option<(usize, f64)> {
+    let (top, top_score) = (0..blocks.len())
+        .filter_map(|i| adjusted_score(&blocks[i]).map(|score| (i, score)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+
+    let is_within = |mut node: usize, ancestor: usize| loop {
+        if node == ancestor {
+            return true;
+        }
+        match blocks[node].parent {
+            Some(parent) => node = parent,
+            None => return false,
+        }
+    };
+    let top_votes = blocks[top].paragraph_votes;
+    let alternatives: Vec<usize> = (0..blocks.len())
+        .filter(|&i| blocks[i].paragraph_votes > 0.0 && blocks[i].paragraph_votes >= top_votes * 0.5)
+        .filter(|&i| !is_within(i, top) && !is_within(top, i))
+        .collect();
+    if alternatives.is_empty() {
+        return Some((top, top_score));
+    }
+
+    let needed = alternatives.len().min(3);
+    let mut ancestor = blocks[top].parent;
+    while let Some(candidate) = ancestor {
+        if alternatives.iter().filter(|&&alt| is_within(alt, candidate)).count() >= needed {
+            let score = adjusted_score(&blocks[candidate]).unwrap_or(0.0).max(top_score);
+            return Some((candidate, score));
+        }
+        ancestor = blocks[candidate].parent;
+    }
+    Some((top, top_score))
+}
+
+/// The top container plus siblings that look like more of the same article
+fn accepted_nodes(blocks: &[Block], top: usize, top_score: f64) -> Vec<usize> {
+    let Some(parent) = blocks[top].parent else {
+        return vec![top];
+    };
+    let threshold = (top_score * 0.2).max(10.0);
+    let min_votes = blocks[top].votes * 0.2;
+
+    let mut accepted = vec![top];
+    for (i, sibling) in blocks.iter().enumerate() {
+        if i == top || sibling.parent != Some(parent) {
+            continue;
+        }
+        // The class bonus alone (e.g. a "c-article-sidebar") doesn't make a sibling content
+        let scored = adjusted_score(sibling).is_some_and(|score| score >= threshold)
+            && sibling.votes * (1.0 - sibling.link_density()) >= min_votes;
+        let len = sibling.text.chars().count();
+        let paragraph = sibling.tag == "p"
+            && ((len > 80 && sibling.link_density() < 0.25)
+                || (len > 0 && sibling.link_density() == 0.0 && sibling.text.ends_with('.')));
+        if scored || paragraph {
+            accepted.push(i);
+        }
+    }
+    accepted
+}
+
+fn class_and_id(element: ElementRef) -> String {
+    let el = element.value();
+    format!("{} {}", el.attr("class").unwrap_or_default(), el.id().unwrap_or_default()).to_lowercase()
+}
+
+fn class_weight(element: ElementRef) -> f64 {
+    let names = class_and_id(element);
+    let mut weight = 0.0;
+    if NEGATIVE.iter().any(|n| names.contains(n)) {
+        weight -= 25.0;
+    }
+    if POSITIVE.iter().any(|p| names.contains(p)) {
+        weight += 25.0;
+    }
+    weight
+}
+
+fn is_unlikely(element: ElementRef) -> bool {
+    if matches!(element.value().name(), "html" | "body" | "main" | "article") {
+        return false;
+    }
+    let names = class_and_id(element);
+    let role = element.value().attr("role").unwrap_or_default();
+    matches!(role, "navigation" | "banner" | "complementary" | "contentinfo" | "dialog")
+        || (UNLIKELY.iter().any(|u| names.contains(u)) && !MAYBE.iter().any(|m| names.contains(m)))
+}
+
+fn is_hidden(element: ElementRef) -> bool {
+    let el = element.value();
+    let style = el.attr("style").unwrap_or_default().replace(' ', "").to_lowercase();
+    el.attr("hidden").is_some()
+        || el.attr("aria-hidden") == Some("true")
+        || style.contains("display:none")
+        || style.contains("visibility:hidden")
+}
+
+fn has_ancestor(element: ElementRef, tags: &[&str]) -> bool {
+    element
+        .ancestors()
+        .filter_map(ElementRef::wrap)
+        .any(|ancestor| tags.contains(&ancestor.value().name()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const CORPUS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/readability");
+
+    /// Every `<page>.html` in the corpus must extract to `<page>.txt`.
+    /// `UPDATE_EXPECTED=1 cargo test readability` rewrites the expected files (review the diff!).
+    #[test]
+    fn corpus_matches_expected_output() {
+        let mut pages: Vec<_> = fs::read_dir(CORPUS)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "html"))
+            .collect();
+        pages.sort();
+        assert!(!pages.is_empty());
+
+        for page in pages {
+            let extracted = extract(&Html::parse_document(&fs::read_to_string(&page).unwrap()));
+            let expected_path = page.with_extension("txt");
+            if std::env::var("UPDATE_EXPECTED").is_ok() {
+                fs::write(&expected_path, format!("{}\n", extracted)).unwrap();
+                continue;
+            }
+            let expected = fs::read_to_string(&expected_path).unwrap();
+            assert_eq!(extracted, expected.trim_end(), "{}", page.display());
+        }
+    }
+
+    #[test]
+    fn nested_containers_do_not_repeat_text() {
+        let html = "<html><body><div><div><div><p>Only once: the text of a deeply nested paragraph, with commas, \
+                    semicolons; and colons.</p></div></div></div></body></html>";
+        let text = extract(&Html::parse_document(html));
+        assert_eq!(
+            text,
+            "Only once: the text of a deeply nested paragraph, with commas, semicolons; and colons."
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use r2d2_redis::redis::{RedisResult, cmd};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::time::sleep;
+
+use crate::common::config::{NEAR_DUP_SKIP, QUICKWIT_INDEX, REINDEX_BATCH, REINDEX_RATE, WARC_DIR};
+use crate::crawler::clean_url;
+use crate::crawler::core::{index_doc, ingest_endpoint, ingest_to_quickwit};
+use crate::crawler::crawl::{CrawlOutcome, PageMetadata, parse_page};
+use crate::crawler::{canonical, neardup, soft404};
+use crate::crawler::warc::{self, WarcReader};
+use crate::db::{get_kv_conn, paths};
+
+/// Pages parsed from one WARC file, and where to resume once they are ingested
+struct Batch {
+    pages: Vec<PageMetadata>,
+    next_offset: u64,
+}
+
+/// `research reindex <index> [--rate docs_per_sec]`
+///
+/// Runs the current extraction pipeline over every archived response in WARC_DIR and ingests the
+/// result into `index`, a fresh index created from site-index.yaml under a new id. Quickwit has no
+/// upsert, so the live index is refused. Only the latest capture of a url is used. Nothing is
+/// refetched: soft-404s are recognised by their wording, and a page moves to its canonical only
+/// when that was crawled or archived too.
+/// Progress is checkpointed per file in `rix:<index>`, so running the same command again resumes.
+pub async fn run(args: &[String]) {
+    let mut index = None;
+    let mut rate = *REINDEX_RATE;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rate" => rate = args.next().and_then(|r| r.parse().ok()).unwrap_or(rate),
+            other => index = Some(other.to_string()),
+        }
+    }
+    let Some(index) = index else {
+        eprintln!("❌ Usage: research reindex <new index id> [--rate docs_per_sec]");
+        return;
+    };
+    if index == QUICKWIT_INDEX {
+        eprintln!("❌ '{}' is the live index; reindexing into it would duplicate every document", index);
+        return;
+    }
+
+    let Some(dir) = WARC_DIR.as_deref() else {
+        eprintln!("❌ WARC_DIR is not set, nothing to reindex");
+        return;
+    };
+    let files = match warc::list_files(Path::new(dir)) {
+        Ok(files) => files,
+        Err(err) => {
+            eprintln!("❌ Cannot read {}: {}", dir, err);
+            return;
+        }
+    };
+
+    println!("♻️ Reindexing {} WARC files into '{}' at {} docs/s", files.len(), index, rate);
+    let checkpoint_key = format!("{}:{}", paths::REINDEX_CHECKPOINT, index);
+
+    for path in files {
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let checkpoint: Option<String> = {
+            let mut kv_conn = get_kv_conn();
+            cmd("HGET").arg(&checkpoint_key).arg(&name).query(&mut *kv_conn).unwrap_or(None)
+        };
+        let offset = match checkpoint.as_deref() {
+            Some("done") => continue,
+            Some(offset) => offset.parse().unwrap_or(0),
+            None => 0,
+        };
+
+        if let Err(err) = reindex_file(path, &name, offset, &index, &checkpoint_key, rate.max(1)).await {
+            // Checkpoint stays at the last ingested batch -> rerun to resume
+            eprintln!("❌ Reindex stopped in {}: {}", name, err);
+            return;
+        }
+        set_checkpoint(&checkpoint_key, &name, "done");
+    }
+
+    println!("✅ Reindex into '{}' complete", index);
+}
+
+async fn reindex_file(
+    path: PathBuf,
+    name: &str,
+    offset: u64,
+    index: &str,
+    checkpoint_key: &str,
+    rate: u32,
+) -> anyhow::Result<()> {
+    println!("📂 {} from offset {}", name, offset);
+
+    // Reading and parsing is blocking work; ingestion waits on it through a small channel
+    let (tx, mut rx) = mpsc::channel::<Batch>(2);
+    let file_name = name.to_string();
+    let reader = tokio::task::spawn_blocking(move || read_batches(&path, &file_name, offset, tx));
+
+    let endpoint = ingest_endpoint(index);
+    let mut total = 0;
+    while let Some(batch) = rx.recv().await {
+        let started = Instant::now();
+        let (mut docs, mut claimed) = (Vec::new(), Vec::new());
+        for mut metadata in batch.pages {
+            // No requests and no live crawl state here: wording-only soft-404s, known canonicals
+            if soft404::heuristic(&metadata).is_some() {
+                continue;
+            }
+            canonical::consolidate_known(&mut metadata);
+            if !claim_document(index, &metadata.url) {
+                continue;
+            }
+            claimed.push(metadata.url.clone());
+            if neardup::assign_cluster(&mut metadata) || !*NEAR_DUP_SKIP {
+                docs.push(index_doc(&metadata));
+            }
+        }
+        let count = docs.len();
+        if count > 0
+            && let Err(err) = ingest_to_quickwit(&Value::Array(docs), &endpoint).await
+        {
+            release_documents(index, &claimed); // the rerun starts over at this batch
+            return Err(err);
+        }
+        set_checkpoint(checkpoint_key, name, &batch.next_offset.to_string());
+
+        total += count;
+        println!("♻️ {} -> {} docs (offset {})", name, total, batch.next_offset);
+
+        // Rate control: a batch of n documents takes at least n / rate seconds
+        let budget = Duration::from_secs_f64(count as f64 / rate as f64);
+        if let Some(remaining) = budget.checked_sub(started.elapsed()) {
+            sleep(remaining).await;
+        }
+    }
+
+    reader.await??;
+    Ok(())
+}
+
+/// Parses response records into indexable pages, REINDEX_BATCH at a time
+fn read_batches(path: &Path, name: &str, offset: u64, tx: mpsc::Sender<Batch>) -> anyhow::Result<()> {
+    let mut pages = Vec::new();
+    let mut next_offset = offset;
+
+    for record in WarcReader::open(path, offset)? {
+        let record = record?;
+        next_offset = record.next_offset;
+
+        let Some(response) = record.to_fetch_response() else { continue };
+        let url = clean_url(&response.url).unwrap_or_else(|| response.url.clone());
+        if !is_latest_capture(&url, name, record.offset) {
+            continue;
+        }
+
+        if let Ok(CrawlOutcome::Fetched(res)) = parse_page(&response)
+            && !res.metadata.directives.noindex
+        {
+            pages.push(res.metadata);
+        }
+
+        if pages.len() >= REINDEX_BATCH {
+            let batch = Batch {
+                pages: std::mem::take(&mut pages),
+                next_offset,
+            };
+            if tx.blocking_send(batch).is_err() {
+                return Ok(()); // ingestion side gave up
+            }
+        }
+    }
+
+    let _ = tx.blocking_send(Batch { pages, next_offset });
+    Ok(())
+}
+
+/// Older captures are skipped; a url missing from the WARC index is taken as is
+fn is_latest_capture(url: &str, file: &str, offset: u64) -> bool {
+    let mut kv_conn = get_kv_conn();
+    let latest: Vec<String> = cmd("ZREVRANGE")
+        .arg(format!("{}:{}", paths::WARC_INDEX, url))
+        .arg(0)
+        .arg(0)
+        .query(&mut *kv_conn)
+        .unwrap_or_default();
+
+    latest
+        .first()
+        .is_none_or(|location| location.starts_with(&format!("{}:{}:", file, offset)))
+}
+
+/// One document per url in the target index: captures of several variants (redirects, canonical
+/// aliases) of the same document are ingested once, as the live crawl's owner key does
+fn claim_document(index: &str, document_url: &str) -> bool {
+    let mut kv_conn = get_kv_conn();
+    let added: i64 = cmd("SADD")
+        .arg(format!("{}:{}", paths::REINDEX_DOCS, index))
+        .arg(document_url)
+        .query(&mut *kv_conn)
+        .unwrap_or(1);
+    added == 1
+}
+
+fn release_documents(index: &str, document_urls: &[String]) {
+    if document_urls.is_empty() {
+        return;
+    }
+    let mut kv_conn = get_kv_conn();
+    let _: RedisResult<()> = cmd("SREM")
+        .arg(format!("{}:{}", paths::REINDEX_DOCS, index))
+        .arg(document_urls)
+        .query(&mut *kv_conn);
+}
+
+fn set_checkpoint(key: &str, file: &str, value: &str) {
+    let mut kv_conn = get_kv_conn();
+    let _: RedisResult<()> = cmd("HSET").arg(key).arg(file).arg(value).query(&mut *kv_conn);
+}
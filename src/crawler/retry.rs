@@ -0,0 +1,72 @@
+use r2d2_redis::redis::{RedisResult, cmd};
+use rand::Rng;
+use std::time::Duration;
+use url::Url;
+
+use crate::common::config::{MAX_FETCH_ATTEMPTS, REQUEUE_BASE_SECS};
+use crate::crawler::fetch::FetchError;
+use crate::crawler::politeness;
+use crate::crawler::utils;
+use crate::db::{get_kv_conn, paths};
+
+/// Exponential backoff with additive jitter: `base * 2^attempt` plus up to another `base`
+pub fn backoff(base: Duration, attempt: u32) -> Duration {
+    let exp = base.saturating_mul(2u32.saturating_pow(attempt.min(16)));
+    let jitter_ms = rand::thread_rng().gen_range(0..=base.as_millis() as u64);
+    exp + Duration::from_millis(jitter_ms)
+}
+
+/// Called once in-process retries are used up: put the url back in the frontier
+/// (via the deferred set) with a growing delay, or give up after MAX_FETCH_ATTEMPTS.
+pub fn requeue_or_give_up(url: &str, err: &FetchError) {
+    count_error(err);
+
+    if !err.is_retryable() {
+        utils::record_skip(url, &err.to_string());
+        clear_attempts(url);
+        return;
+    }
+
+    let mut conn = get_kv_conn();
+    let attempts: u32 = cmd("HINCRBY")
+        .arg(paths::ATTEMPTS)
+        .arg(url)
+        .arg(1)
+        .query(&mut *conn)
+        .unwrap_or(MAX_FETCH_ATTEMPTS);
+
+    if attempts >= MAX_FETCH_ATTEMPTS {
+        println!("💀 Giving up on {} after {} attempts ({})", url, attempts, err);
+        utils::record_skip(url, &format!("gave up after {} attempts: {}", attempts, err));
+        clear_attempts(url);
+        return;
+    }
+
+    let wait = match err {
+        // The server told us how long: park the whole host, retry the url right after
+        FetchError::RateLimited { retry_after, .. } => {
+            if let Some(host) = Url::parse(url).ok().and_then(|u| u.host_str().map(|h| h.to_string())) {
+                politeness::back_off(&host, *retry_after);
+            }
+            *retry_after
+        }
+        _ => backoff(Duration::from_secs(REQUEUE_BASE_SECS), attempts),
+    };
+
+    println!("🔁 Requeue {} in {}s (attempt {}, {})", url, wait.as_secs(), attempts, err);
+    politeness::defer(url, wait.as_millis() as u64);
+}
+
+/// Forgets the attempt counter once a url was fetched (or dropped)
+pub fn clear_attempts(url: &str) {
+    let mut conn = get_kv_conn();
+    let _: RedisResult<()> = cmd("HDEL").arg(paths::ATTEMPTS).arg(url).query(&mut *conn);
+}
+
+/// Per-class failure counters (`stats:fetch_err:<kind>`)
+fn count_error(err: &FetchError) {
+    let mut conn = get_kv_conn();
+    let _: RedisResult<()> = cmd("INCR")
+        .arg(format!("{}:{}", paths::STATS_FETCH_ERRORS, err.kind()))
+        .query(&mut *conn);
+}
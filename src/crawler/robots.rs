@@ -0,0 +1,310 @@
+use r2d2_redis::redis::{RedisResult, cmd};
+use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use url::Url;
+
+use crate::common::config::{ROBOTS_MAX_BYTES, ROBOTS_TTL_SECS, ROBOTS_UNREACHABLE_TTL_SECS};
+use crate::common::utils::user_agent;
+use crate::crawler::fetch::{get_following, read_body_capped};
+use crate::db::{get_kv_conn, paths};
+
+/// Parsed robots.txt for a single origin (scheme + host + port).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RobotsRules {
+    pub groups: Vec<RobotsGroup>,
+    pub sitemaps: Vec<String>,
+    /// robots.txt could not be fetched (5xx / network error) -> treat the whole site as disallowed
+    pub unreachable: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RobotsGroup {
+    pub agents: Vec<String>, // lowercased user-agent tokens
+    pub rules: Vec<RobotsRule>,
+    pub crawl_delay: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RobotsRule {
+    pub allow: bool,
+    pub pattern: String,
+}
+
+impl RobotsRules {
+    /// Parses a robots.txt body (RFC 9309 + common `Crawl-delay` / `Sitemap` extensions)
+    pub fn parse(body: &str) -> RobotsRules {
+        let mut rules = RobotsRules::default();
+        let mut current: Option<RobotsGroup> = None;
+        let mut in_agent_lines = false; // consecutive user-agent lines share one group
+
+        for raw_line in body.lines() {
+            let line = raw_line.split('#').next().unwrap_or("").trim();
+            let Some((key, value)) = line.split_once(':') else { continue };
+            let key = key.trim().to_lowercase();
+            let value = value.trim();
+
+            match key.as_str() {
+                "user-agent" => {
+                    if !in_agent_lines {
+                        if let Some(group) = current.take() {
+                            rules.groups.push(group);
+                        }
+                        current = Some(RobotsGroup::default());
+                    }
+                    if let Some(group) = current.as_mut() {
+                        group.agents.push(value.to_lowercase());
+                    }
+                    in_agent_lines = true;
+                }
+                "allow" | "disallow" => {
+                    in_agent_lines = false;
+                    // Empty `Disallow:` means "allow everything" -> no rule needed
+                    if let (Some(group), false) = (current.as_mut(), value.is_empty()) {
+                        group.rules.push(RobotsRule {
+                            allow: key == "allow",
+                            pattern: value.to_string(),
+                        });
+                    }
+                }
+                "crawl-delay" => {
+                    in_agent_lines = false;
+                    if let Some(group) = current.as_mut() {
+                        group.crawl_delay = value.parse::<f64>().ok().filter(|d| *d >= 0.0);
+                    }
+                }
+                // Sitemap lines are global, not part of any group
+                "sitemap" if !value.is_empty() => rules.sitemaps.push(value.to_string()),
+                _ => {}
+            }
+        }
+
+        if let Some(group) = current.take() {
+            rules.groups.push(group);
+        }
+        rules
+    }
+
+    /// Groups that apply to `agent`: every group naming its product token, otherwise the `*` groups
+    fn groups_for(&self, agent: &str) -> Vec<&RobotsGroup> {
+        let token = product_token(agent).to_lowercase();
+        let specific: Vec<&RobotsGroup> = self
+            .groups
+            .iter()
+            .filter(|g| g.agents.iter().any(|a| !token.is_empty() && product_token(a) == token))
+            .collect();
+
+        if !specific.is_empty() {
+            return specific;
+        }
+        self.groups
+            .iter()
+            .filter(|g| g.agents.iter().any(|a| a == "*"))
+            .collect()
+    }
+
+    /// Returns `Some(reason)` when `url` may not be crawled by `agent`
+    pub fn disallow_reason(&self, agent: &str, url: &Url) -> Option<String> {
+        if self.unreachable {
+            return Some("robots.txt unreachable".to_string());
+        }
+
+        let mut path = url.path().to_string();
+        if path == "/robots.txt" {
+            return None;
+        }
+        if let Some(query) = url.query() {
+            path.push('?');
+            path.push_str(query);
+        }
+
+        // Longest matching pattern wins, Allow wins ties
+        let mut best: Option<&RobotsRule> = None;
+        for group in self.groups_for(agent) {
+            for rule in &group.rules {
+                if !pattern_matches(&rule.pattern, &path) {
+                    continue;
+                }
+                let better = match best {
+                    None => true,
+                    Some(b) => {
+                        rule.pattern.len() > b.pattern.len()
+                            || (rule.pattern.len() == b.pattern.len() && rule.allow && !b.allow)
+                    }
+                };
+                if better {
+                    best = Some(rule);
+                }
+            }
+        }
+
+        match best {
+            Some(rule) if !rule.allow => Some(format!("robots.txt Disallow: {}", rule.pattern)),
+            _ => None,
+        }
+    }
+
+    /// Largest `Crawl-delay` declared for `agent`, in seconds
+    pub fn crawl_delay(&self, agent: &str) -> Option<f64> {
+        self.groups_for(agent)
+            .iter()
+            .filter_map(|g| g.crawl_delay)
+            .reduce(f64::max)
+    }
+}
+
+/// `ReSearchBot/0.1 (+https://..)` -> `ReSearchBot` (RFC 9309 matches user-agent lines on this token)
+fn product_token(agent: &str) -> &str {
+    agent.trim().split(['/', ' ']).next().unwrap_or_default()
+}
+
+/// Matches a robots.txt path pattern supporting `*` wildcards and a trailing `$` anchor
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(p) => (p, true),
+        None => (pattern, false),
+    };
+
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let first = parts[0];
+    if !path.starts_with(first) {
+        return false;
+    }
+    if parts.len() == 1 {
+        return !anchored || path.len() == first.len();
+    }
+
+    let mut pos = first.len();
+    let last_idx = parts.len() - 1;
+    for (i, part) in parts.iter().enumerate().skip(1) {
+        if i == last_idx && anchored {
+            return path.len() >= pos + part.len() && path.ends_with(part);
+        }
+        match path[pos..].find(part) {
+            Some(found) => pos += found + part.len(),
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Fetches (or loads from kvrocks) the robots.txt rules for the origin of `url`
+pub async fn rules_for(url: &Url) -> RobotsRules {
+    let origin = url.origin().ascii_serialization();
+    let cache_key = format!("{}:{}", paths::ROBOTS_CACHE, origin);
+
+    {
+        let mut conn = get_kv_conn();
+        let cached: Option<String> = cmd("GET").arg(&cache_key).query(&mut *conn).unwrap_or(None);
+        if let Some(rules) = cached.and_then(|json| serde_json::from_str(&json).ok()) {
+            return rules;
+        }
+    }
+
+    let rules = fetch_robots(&origin).await;
+    let ttl = if rules.unreachable {
+        ROBOTS_UNREACHABLE_TTL_SECS
+    } else {
+        ROBOTS_TTL_SECS
+    };
+
+    if let Ok(json) = serde_json::to_string(&rules) {
+        let mut conn = get_kv_conn();
+        let _: RedisResult<()> = cmd("SET")
+            .arg(&cache_key)
+            .arg(json)
+            .arg("EX")
+            .arg(ttl)
+            .query(&mut *conn);
+    }
+
+    rules
+}
+
+async fn fetch_robots(origin: &str) -> RobotsRules {
+    let robots_url = format!("{}/robots.txt", origin);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(USER_AGENT, HeaderValue::from_static(user_agent(&robots_url)));
+
+    let mut resp = match get_following(&robots_url, headers, HeaderMap::new(), Some(Duration::from_secs(15))).await {
+        Ok((resp, _)) => resp,
+        Err(err) => {
+            println!("⚠️ robots.txt fetch failed {}: {}", robots_url, err);
+            return RobotsRules { unreachable: true, ..Default::default() };
+        }
+    };
+
+    let status = resp.status();
+    if status.is_success() {
+        // Anything past ROBOTS_MAX_BYTES is ignored, so it is not downloaded either
+        match read_body_capped(&mut resp, ROBOTS_MAX_BYTES).await {
+            Ok((body, _)) => RobotsRules::parse(&String::from_utf8_lossy(&body)),
+            Err(_) => RobotsRules { unreachable: true, ..Default::default() },
+        }
+    } else if status.as_u16() == 429 || status.is_server_error() {
+        // Server trouble: back off from the whole site until the short TTL expires
+        RobotsRules { unreachable: true, ..Default::default() }
+    } else {
+        // 4xx (404, 403, ...) -> no restrictions
+        RobotsRules::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowed(robots: &str, agent: &str, url: &str) -> bool {
+        RobotsRules::parse(robots).disallow_reason(agent, &Url::parse(url).unwrap()).is_none()
+    }
+
+    #[test]
+    fn patterns_support_wildcards_and_anchors() {
+        assert!(pattern_matches("/papers", "/papers/1"));
+        assert!(!pattern_matches("/papers", "/paper"));
+        assert!(pattern_matches("/*.pdf", "/files/a.pdf"));
+        assert!(pattern_matches("/*.pdf", "/files/a.pdf?download=1"));
+        assert!(pattern_matches("/*.pdf$", "/files/a.pdf"));
+        assert!(!pattern_matches("/*.pdf$", "/files/a.pdf?download=1"));
+        assert!(pattern_matches("/search$", "/search"));
+        assert!(!pattern_matches("/search$", "/search/x"));
+        assert!(pattern_matches("/a*b*c", "/a-x-b-y-c-z"));
+        assert!(!pattern_matches("/a*b*c", "/a-x-c-y-b"));
+    }
+
+    #[test]
+    fn parses_groups_delays_and_sitemaps() {
+        let rules = RobotsRules::parse(
+            "User-agent: a\nUser-agent: B # comment\nDisallow: /x\nCrawl-delay: 2.5\n\n\
+             User-agent: *\nDisallow:\nAllow: /y\nSitemap: https://example.org/sitemap.xml\n",
+        );
+        assert_eq!(rules.groups.len(), 2);
+        assert_eq!(rules.groups[0].agents, vec!["a", "b"]);
+        assert_eq!(rules.groups[0].rules.len(), 1);
+        assert_eq!(rules.groups[0].crawl_delay, Some(2.5));
+        assert_eq!(rules.groups[1].rules.len(), 1); // empty Disallow adds no rule
+        assert_eq!(rules.sitemaps, vec!["https://example.org/sitemap.xml"]);
+        assert_eq!(rules.crawl_delay("B/1.0"), Some(2.5));
+        assert_eq!(rules.crawl_delay("other"), None);
+    }
+
+    #[test]
+    fn longest_match_wins_and_allow_wins_ties() {
+        let robots = "User-agent: *\nDisallow: /papers\nAllow: /papers/open\nDisallow: /tie\nAllow: /tie\n";
+        assert!(!allowed(robots, "ReSearchBot", "https://example.org/papers/closed"));
+        assert!(allowed(robots, "ReSearchBot", "https://example.org/papers/open/1"));
+        assert!(allowed(robots, "ReSearchBot", "https://example.org/tie"));
+        assert!(allowed(robots, "ReSearchBot", "https://example.org/robots.txt"));
+    }
+
+    #[test]
+    fn groups_match_the_product_token_exactly() {
+        let robots = "User-agent: *\nDisallow: /\n\nUser-agent: researchbot\nAllow: /\n\n\
+                      User-agent:\nUser-agent: r\nAllow: /\n";
+        assert!(allowed(robots, "ReSearchBot/0.1 (+https://example.org)", "https://example.org/a"));
+        // Neither an empty nor a partial user-agent line may capture other crawlers
+        assert!(!allowed(robots, "OtherBot/1.0", "https://example.org/a"));
+        assert!(!allowed(robots, "ReSearchBotX", "https://example.org/a"));
+    }
+}
@@ -0,0 +1,177 @@
+use url::Url;
+
+/// Bibliographic metadata from Highwire Press `citation_*` tags (Google Scholar's format, used by
+/// most publishers and preprint servers), with Dublin Core `DC.*` / `DCTERMS.*` as fallback.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScholarlyMetadata {
+    pub title: Option<String>,
+    pub authors: Vec<String>, // in citation order
+    pub doi: Option<String>,  // bare, e.g. `10.1007/s11104-023-05912-1`
+    pub publication_date: Option<String>, // as published, `/` normalised to `-` (may be just a year)
+    pub journal: Option<String>,          // journal or conference
+    pub publisher: Option<String>,
+    pub pdf_url: Option<String>, // absolute
+    pub abstract_text: Option<String>,
+    pub keywords: Vec<String>,
+}
+
+impl ScholarlyMetadata {
+    /// From every `(lowercased name, content)` meta tag of a page at `page_url`
+    pub fn from_meta(meta: &[(String, String)], page_url: &str) -> Self {
+        let first = |names: &[&str]| {
+            names.iter().find_map(|name| {
+                meta.iter()
+                    .find(|(n, content)| n == name && !content.trim().is_empty())
+                    .map(|(_, content)| content.trim().to_string())
+            })
+        };
+        let all = |name: &str| -> Vec<String> {
+            meta.iter()
+                .filter(|(n, _)| n == name)
+                .map(|(_, content)| content.trim().to_string())
+                .filter(|content| !content.is_empty())
+                .collect()
+        };
+
+        let mut authors = all("citation_author");
+        if authors.is_empty() {
+            authors = first(&["citation_authors"])
+                .map(|list| split_list(&list, ';'))
+                .unwrap_or_default();
+        }
+        if authors.is_empty() {
+            authors = [all("dc.creator"), all("dcterms.creator")].concat();
+        }
+
+        let mut keywords = first(&["citation_keywords"])
+            .map(|list| split_list(&list, if list.contains(';') { ';' } else { ',' }))
+            .unwrap_or_default();
+        if keywords.is_empty() {
+            keywords = [all("dc.subject"), all("dcterms.subject")].concat();
+        }
+
+        let doi = first(&["citation_doi"])
+            .or_else(|| {
+                ["dc.identifier", "dcterms.identifier"]
+                    .iter()
+                    .flat_map(|name| all(name))
+                    .find(|id| normalize_doi(id).is_some())
+            })
+            .and_then(|doi| normalize_doi(&doi));
+
+        let pdf_url = first(&["citation_pdf_url"]).and_then(|href| {
+            Url::parse(page_url)
+                .and_then(|base| base.join(&href))
+                .ok()
+                .map(|url| url.to_string())
+        });
+
+        ScholarlyMetadata {
+            title: first(&["citation_title", "dc.title", "dcterms.title"]),
+            authors,
+            doi,
+            publication_date: first(&[
+                "citation_publication_date",
+                "citation_date",
+                "citation_online_date",
+                "dc.date.issued",
+                "dcterms.issued",
+                "dc.date",
+                "dcterms.date",
+            ])
+            .map(|date| date.replace('/', "-")),
+            journal: first(&[
+                "citation_journal_title",
+                "citation_conference_title",
+                "citation_inbook_title",
+                "dc.relation.ispartof",
+                "dcterms.ispartof",
+            ]),
+            publisher: first(&["citation_publisher", "dc.publisher", "dcterms.publisher"]),
+            pdf_url,
+            abstract_text: first(&["citation_abstract", "dcterms.abstract", "dc.description", "dcterms.description"]),
+            keywords,
+        }
+    }
+}
+
+fn split_list(list: &str, separator: char) -> Vec<String> {
+    list.split(separator)
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// `doi:10.x/y`, `https://doi.org/10.x/y` or `10.x/y` -> `10.x/y`
+pub fn normalize_doi(id: &str) -> Option<String> {
+    let id = id.trim();
+    let lower = id.to_lowercase();
+    let start = ["https://doi.org/", "http://doi.org/", "https://dx.doi.org/", "http://dx.doi.org/", "doi:"]
+        .iter()
+        .find(|prefix| lower.starts_with(*prefix))
+        .map_or(0, |prefix| prefix.len());
+    let doi = id[start..].trim();
+    (doi.starts_with("10.") && doi.contains('/')).then(|| doi.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(tags: &[(&str, &str)]) -> Vec<(String, String)> {
+        tags.iter().map(|(name, content)| (name.to_string(), content.to_string())).collect()
+    }
+
+    #[test]
+    fn highwire_tags_are_extracted() {
+        let tags = meta(&[
+            ("citation_title", " Crawling at Scale "),
+            ("citation_author", "Ada Lovelace"),
+            ("citation_author", "Alan Turing"),
+            ("citation_doi", "doi:10.5555/fixture.2"),
+            ("citation_publication_date", "2023/03/12"),
+            ("citation_journal_title", "Fixture Journal"),
+            ("citation_pdf_url", "2.pdf"),
+            ("citation_keywords", "crawling; politeness"),
+            ("dc.title", "Ignored"),
+        ]);
+        let scholarly = ScholarlyMetadata::from_meta(&tags, "https://fixture.test/papers/2");
+        assert_eq!(scholarly.title.as_deref(), Some("Crawling at Scale"));
+        assert_eq!(scholarly.authors, vec!["Ada Lovelace", "Alan Turing"]);
+        assert_eq!(scholarly.doi.as_deref(), Some("10.5555/fixture.2"));
+        assert_eq!(scholarly.publication_date.as_deref(), Some("2023-03-12"));
+        assert_eq!(scholarly.journal.as_deref(), Some("Fixture Journal"));
+        assert_eq!(scholarly.pdf_url.as_deref(), Some("https://fixture.test/papers/2.pdf"));
+        assert_eq!(scholarly.keywords, vec!["crawling", "politeness"]);
+    }
+
+    #[test]
+    fn dublin_core_is_the_fallback() {
+        let tags = meta(&[
+            ("dc.title", "Field Notes"),
+            ("dc.creator", "Grace Hopper"),
+            ("dcterms.creator", "Katherine Johnson"),
+            ("dc.identifier", "urn:isbn:0-000-00000-0"),
+            ("dc.identifier", "https://doi.org/10.1234/notes"),
+            ("dc.subject", "navigation"),
+            ("dc.date", "1961"),
+        ]);
+        let scholarly = ScholarlyMetadata::from_meta(&tags, "https://fixture.test/notes");
+        assert_eq!(scholarly.title.as_deref(), Some("Field Notes"));
+        assert_eq!(scholarly.authors, vec!["Grace Hopper", "Katherine Johnson"]);
+        assert_eq!(scholarly.doi.as_deref(), Some("10.1234/notes"));
+        assert_eq!(scholarly.keywords, vec!["navigation"]);
+        assert_eq!(scholarly.publication_date.as_deref(), Some("1961"));
+
+        assert_eq!(ScholarlyMetadata::from_meta(&meta(&[("description", "A lab")]), ""), Default::default());
+    }
+
+    #[test]
+    fn dois_are_normalised() {
+        assert_eq!(normalize_doi("10.1007/s11104-023-05912-1").as_deref(), Some("10.1007/s11104-023-05912-1"));
+        assert_eq!(normalize_doi("DOI:10.1/x").as_deref(), Some("10.1/x"));
+        assert_eq!(normalize_doi(" https://dx.doi.org/10.1/X ").as_deref(), Some("10.1/X"));
+        assert_eq!(normalize_doi("https://example.org/10.1/x"), None);
+        assert_eq!(normalize_doi("10.1"), None);
+    }
+}
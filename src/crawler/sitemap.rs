@@ -0,0 +1,321 @@
+use chrono::{DateTime, NaiveDate};
+use flate2::read::GzDecoder;
+use quick_xml::Reader;
+use quick_xml::events::Event;
+use r2d2_redis::redis::cmd;
+use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
+use std::collections::{HashSet, VecDeque};
+use std::io::Read;
+use std::time::Duration;
+use url::Url;
+
+use crate::common::config::{
+    CRAWLER_TOKEN, SITEMAP_HIGH_PRIORITY, SITEMAP_MAX_BYTES, SITEMAP_MAX_FILES, SITEMAP_MAX_URLS, SITEMAP_TTL_SECS,
+};
+use crate::common::utils::user_agent;
+use crate::crawler::clean_url;
+use crate::crawler::core::{Priority, enqueue_and_mark_seen, requeue_seen};
+use crate::crawler::fetch::{get_following, read_body_capped};
+use crate::crawler::politeness;
+use crate::crawler::robots::RobotsRules;
+use crate::crawler::utils;
+use crate::db::{get_kv_conn, paths};
+
+/// A `<url>` (or `<sitemap>`) entry
+#[derive(Debug, Clone, Default)]
+pub struct SitemapEntry {
+    pub loc: String,
+    pub lastmod: Option<i64>, // unix seconds
+    pub priority: Option<f32>,
+}
+
+#[derive(Debug, Default)]
+pub struct ParsedSitemap {
+    pub urls: Vec<SitemapEntry>,
+    pub sitemaps: Vec<SitemapEntry>, // children of a sitemap index
+}
+
+/// Starts sitemap ingestion for the origin of `url`, at most once per SITEMAP_TTL_SECS
+/// across all crawler tasks and processes.
+pub fn discover_once(url: &Url, rules: &RobotsRules) {
+    let origin = url.origin().ascii_serialization();
+
+    let mut conn = get_kv_conn();
+    let claimed: Option<String> = cmd("SET")
+        .arg(format!("{}:{}", paths::SITEMAP_DONE, origin))
+        .arg(1)
+        .arg("NX")
+        .arg("EX")
+        .arg(SITEMAP_TTL_SECS)
+        .query(&mut *conn)
+        .unwrap_or(None);
+
+    if claimed.is_none() {
+        return;
+    }
+
+    let host = url.host_str().unwrap_or_default().to_string();
+    let mut roots = rules.sitemaps.clone();
+    roots.push(format!("{}/sitemap.xml", origin));
+    let rules = rules.clone();
+
+    tokio::spawn(async move {
+        ingest_sitemaps(&host, roots, &rules).await;
+    });
+}
+
+/// Walks sitemap indexes breadth first and enqueues the page urls they list.
+/// Only sitemaps on `host` are fetched (under its politeness slot and robots.txt rules).
+async fn ingest_sitemaps(host: &str, roots: Vec<String>, rules: &RobotsRules) {
+    let delay_ms = politeness::host_delay_ms(rules);
+    let mut queue: VecDeque<String> = roots.into_iter().collect();
+    let mut visited = HashSet::new();
+    let mut entries: Vec<SitemapEntry> = Vec::new();
+
+    while let Some(sitemap_url) = queue.pop_front() {
+        if visited.len() >= SITEMAP_MAX_FILES || entries.len() >= SITEMAP_MAX_URLS {
+            break;
+        }
+        if !visited.insert(sitemap_url.clone()) {
+            continue;
+        }
+        let Some(parsed_url) = Url::parse(&sitemap_url).ok().filter(|u| u.host_str() == Some(host)) else {
+            println!("🗺️ Skipped sitemap {} (not on {})", sitemap_url, host);
+            continue;
+        };
+        if let Some(reason) = rules.disallow_reason(&CRAWLER_TOKEN, &parsed_url) {
+            println!("🤖 Skipped sitemap {} -> {}", sitemap_url, reason);
+            continue;
+        }
+
+        politeness::wait_for_slot(host, delay_ms).await;
+        let Some(body) = fetch_sitemap(&sitemap_url).await else { continue };
+
+        let parsed = parse_sitemap(&body);
+        println!(
+            "🗺️ Sitemap {} -> {} urls, {} child sitemaps",
+            sitemap_url,
+            parsed.urls.len(),
+            parsed.sitemaps.len()
+        );
+
+        // Sitemaps may only list urls (and child sitemaps) of their own host
+        let on_host = |e: &SitemapEntry| Url::parse(&e.loc).ok().is_some_and(|u| u.host_str() == Some(host));
+        queue.extend(parsed.sitemaps.into_iter().filter(on_host).map(|s| s.loc));
+        entries.extend(parsed.urls.into_iter().filter(on_host));
+    }
+
+    entries.truncate(SITEMAP_MAX_URLS);
+    let _ = tokio::task::spawn_blocking(move || enqueue_entries(entries)).await;
+}
+
+async fn fetch_sitemap(sitemap_url: &str) -> Option<Vec<u8>> {
+    let mut headers = HeaderMap::new();
+    headers.insert(USER_AGENT, HeaderValue::from_static(user_agent(sitemap_url)));
+
+    let (mut resp, _) = get_following(sitemap_url, headers, HeaderMap::new(), Some(Duration::from_secs(60)))
+        .await
+        .ok()?;
+    if !resp.status().is_success() {
+        return None;
+    }
+
+    let (body, truncated) = read_body_capped(&mut resp, SITEMAP_MAX_BYTES).await.ok()?;
+    if truncated {
+        println!("⚠️ Sitemap {} over {} bytes, ignoring", sitemap_url, SITEMAP_MAX_BYTES);
+        return None;
+    }
+
+    gunzip_if_needed(body)
+}
+
+/// `.xml.gz` files come as raw gzip (no Content-Encoding), so reqwest leaves them alone
+fn gunzip_if_needed(body: Vec<u8>) -> Option<Vec<u8>> {
+    if body.starts_with(&[0x1f, 0x8b]) {
+        let mut xml = Vec::new();
+        GzDecoder::new(&body[..])
+            .take(SITEMAP_MAX_BYTES as u64)
+            .read_to_end(&mut xml)
+            .ok()?;
+        return Some(xml);
+    }
+    Some(body)
+}
+
+/// Parses both `<urlset>` and `<sitemapindex>` documents
+pub fn parse_sitemap(xml: &[u8]) -> ParsedSitemap {
+    let mut reader = Reader::from_reader(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut parsed = ParsedSitemap::default();
+    let mut buf = Vec::new();
+    let mut current: Option<SitemapEntry> = None;
+    let mut field: Option<String> = None;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                let name = String::from_utf8_lossy(e.local_name().as_ref()).to_lowercase();
+                match name.as_str() {
+                    "url" | "sitemap" => current = Some(SitemapEntry::default()),
+                    "loc" | "lastmod" | "priority" => field = Some(name),
+                    _ => {}
+                }
+            }
+            Ok(Event::Text(t)) => {
+                if let (Some(entry), Some(field)) = (current.as_mut(), field.as_deref()) {
+                    let value = t.unescape().map(|v| v.trim().to_string()).unwrap_or_default();
+                    set_field(entry, field, &value);
+                }
+            }
+            Ok(Event::CData(t)) => {
+                if let (Some(entry), Some(field)) = (current.as_mut(), field.as_deref()) {
+                    let value = String::from_utf8_lossy(&t).trim().to_string();
+                    set_field(entry, field, &value);
+                }
+            }
+            Ok(Event::End(e)) => {
+                let name = String::from_utf8_lossy(e.local_name().as_ref()).to_lowercase();
+                match name.as_str() {
+                    "url" | "sitemap" => {
+                        if let Some(entry) = current.take().filter(|e| !e.loc.is_empty()) {
+                            if name == "url" {
+                                parsed.urls.push(entry);
+                            } else {
+                                parsed.sitemaps.push(entry);
+                            }
+                        }
+                    }
+                    "loc" | "lastmod" | "priority" => field = None,
+                    _ => {}
+                }
+            }
+            Ok(Event::Eof) | Err(_) => break, // keep whatever parsed before a syntax error
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    parsed
+}
+
+fn set_field(entry: &mut SitemapEntry, field: &str, value: &str) {
+    match field {
+        "loc" => entry.loc = value.to_string(),
+        "lastmod" => entry.lastmod = parse_lastmod(value),
+        "priority" => entry.priority = value.parse().ok(),
+        _ => {}
+    }
+}
+
+/// W3C datetime: full timestamp or just a date
+fn parse_lastmod(value: &str) -> Option<i64> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.timestamp())
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(value.get(..10)?, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+                .map(|dt| dt.and_utc().timestamp())
+        })
+}
+
+/// Pushes sitemap urls into the frontier through the `crawl_seen` filter.
+/// High `<priority>` urls jump the queue; already-seen urls whose `<lastmod>` is newer
+/// than our last crawl are queued again.
+fn enqueue_entries(mut entries: Vec<SitemapEntry>) {
+    // Most important and most recently changed first
+    entries.sort_by(|a, b| {
+        b.priority
+            .unwrap_or(0.5)
+            .total_cmp(&a.priority.unwrap_or(0.5))
+            .then(b.lastmod.cmp(&a.lastmod))
+    });
+
+    let (mut high, mut normal) = (Vec::new(), Vec::new());
+    for entry in entries {
+        let Some(url) = clean_url(&entry.loc) else { continue };
+        let hash = utils::url_hash(&url);
+        if entry.priority.unwrap_or(0.5) >= SITEMAP_HIGH_PRIORITY {
+            high.push((url, hash, entry.lastmod));
+        } else {
+            normal.push((url, hash, entry.lastmod));
+        }
+    }
+
+    let mut conn = get_kv_conn();
+    for (priority, batch) in [(Priority::High, high), (Priority::Normal, normal)] {
+        // Changed since we last crawled it -> recrawl even though the filter has it
+        let urls: Vec<String> = batch.iter().map(|(url, _, _)| url.clone()).collect();
+        let crawled_at = utils::last_crawled_many(&urls);
+        let changed: Vec<String> = batch
+            .iter()
+            .zip(crawled_at)
+            .filter(|((_, _, lastmod), crawled_at)| matches!((lastmod, crawled_at), (Some(m), Some(c)) if m > c))
+            .map(|((url, _, _), _)| url.clone())
+            .collect();
+
+        let new_urls: Vec<(String, String)> = batch.into_iter().map(|(url, hash, _)| (url, hash)).collect();
+        if let Err(err) = enqueue_and_mark_seen(&new_urls, priority, 0, &mut conn) {
+            eprintln!("⚠️ Sitemap enqueue failed: {}", err);
+        }
+        if let Err(err) = requeue_seen(&changed, priority, &mut conn) {
+            eprintln!("⚠️ Sitemap requeue failed: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    const URLSET: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <url><loc>https://example.org/a?x=1&amp;y=2</loc><lastmod>2024-05-01</lastmod><priority>0.9</priority></url>
+  <url><loc><![CDATA[https://example.org/b]]></loc><lastmod>2024-05-01T12:00:00+02:00</lastmod></url>
+  <url><lastmod>2024-05-01</lastmod></url>
+</urlset>"#;
+
+    #[test]
+    fn parses_urlsets() {
+        let parsed = parse_sitemap(URLSET.as_bytes());
+        assert!(parsed.sitemaps.is_empty());
+        assert_eq!(parsed.urls.len(), 2); // the entry without <loc> is dropped
+
+        assert_eq!(parsed.urls[0].loc, "https://example.org/a?x=1&y=2");
+        assert_eq!(parsed.urls[0].lastmod, Some(1714521600));
+        assert_eq!(parsed.urls[0].priority, Some(0.9));
+
+        // CDATA locations, timestamps with an offset
+        assert_eq!(parsed.urls[1].loc, "https://example.org/b");
+        assert_eq!(parsed.urls[1].lastmod, Some(1714557600));
+        assert_eq!(parsed.urls[1].priority, None);
+    }
+
+    #[test]
+    fn parses_sitemap_indexes() {
+        let index = r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <sitemap><loc>https://example.org/sitemap-1.xml.gz</loc><lastmod>2024-01-01</lastmod></sitemap>
+  <sitemap><loc> https://example.org/sitemap-2.xml </loc></sitemap>
+</sitemapindex>"#;
+        let parsed = parse_sitemap(index.as_bytes());
+        assert!(parsed.urls.is_empty());
+        let children: Vec<&str> = parsed.sitemaps.iter().map(|s| s.loc.as_str()).collect();
+        assert_eq!(children, vec!["https://example.org/sitemap-1.xml.gz", "https://example.org/sitemap-2.xml"]);
+    }
+
+    #[test]
+    fn gzipped_sitemaps_are_decompressed() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(URLSET.as_bytes()).unwrap();
+        let xml = gunzip_if_needed(encoder.finish().unwrap()).unwrap();
+        assert_eq!(parse_sitemap(&xml).urls.len(), 2);
+
+        // Plain XML passes through untouched
+        assert_eq!(gunzip_if_needed(URLSET.as_bytes().to_vec()).unwrap(), URLSET.as_bytes());
+    }
+}
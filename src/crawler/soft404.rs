@@ -0,0 +1,190 @@
+use r2d2_redis::redis::{RedisResult, cmd, pipe};
+use rand::Rng;
+use rand::distributions::Alphanumeric;
+use url::Url;
+
+use crate::common::config::{CRAWLER_TOKEN, NEAR_DUP_MAX_DISTANCE, SOFT404_MAX_TEXT, SOFT404_PROBE_TTL_SECS};
+use crate::crawler::crawl::{CrawlOutcome, PageMetadata, Validators, parse_page};
+use crate::crawler::fetcher::FETCHER;
+use crate::crawler::{neardup, politeness, robots};
+use crate::db::{get_kv_conn, paths};
+
+/// Titles of "not found", "no results" and maintenance pages
+const ERROR_TITLES: [&str; 10] = [
+    "page not found",
+    "404 not found",
+    "error 404",
+    "404 error",
+    "page does not exist",
+    "page doesn't exist",
+    "no results found",
+    "nothing found",
+    "under maintenance",
+    "down for maintenance",
+];
+
+/// Phrases error templates put in the body text
+const ERROR_TEXTS: [&str; 9] = [
+    "the page you requested could not be found",
+    "the page you are looking for could not be found",
+    "the page you're looking for could not be found",
+    "the page you are looking for does not exist",
+    "the requested url was not found on this server",
+    "your search did not match any",
+    "no results were found for",
+    "we are currently performing scheduled maintenance",
+    "we'll be back shortly",
+];
+
+/// Probe cache value for hosts that answer unknown urls with a real error (or a redirect)
+const NO_TEMPLATE: &str = "none";
+/// Probe cache value while another task is probing the host
+const PROBING: &str = "pending";
+
+/// Why a 200 page is really an error page: `Some("template" | "title" | "text")`.
+///
+/// `template`: the page looks like what the host serves for a url that cannot exist,
+/// learned by probing the host once with a random path. `title` / `text`: the usual
+/// not-found / no-results / maintenance wording on a short page.
+pub async fn check(metadata: &PageMetadata) -> Option<&'static str> {
+    if let Some(reason) = heuristic(metadata) {
+        return Some(reason);
+    }
+
+    let parsed = Url::parse(&metadata.url).ok()?;
+    if parsed.path() == "/" {
+        return None; // hosts that serve their home page for unknown urls
+    }
+    let template = error_template(&parsed).await?;
+    let fingerprint = neardup::simhash_min(metadata.cleaned_text.as_deref()?, 1)?;
+    ((fingerprint ^ template).count_ones() <= NEAR_DUP_MAX_DISTANCE).then_some("template")
+}
+
+/// Title / text checks only, no network (used on imported archives)
+pub fn heuristic(metadata: &PageMetadata) -> Option<&'static str> {
+    let text = metadata.cleaned_text.as_deref().unwrap_or_default();
+    if text.chars().count() > SOFT404_MAX_TEXT {
+        return None; // real articles may well mention "page not found"
+    }
+
+    let title = metadata.title.as_deref().unwrap_or_default().trim().to_lowercase();
+    if title.starts_with("404") || ERROR_TITLES.iter().any(|error| title.contains(error)) {
+        return Some("title");
+    }
+
+    let text = text.to_lowercase();
+    ERROR_TEXTS.iter().any(|error| text.contains(error)).then_some("text")
+}
+
+/// Counts a dropped page for its host (`s4:<host>` hash: detected + one field per reason)
+pub fn record(url: &str, reason: &str) {
+    let Some(host) = Url::parse(url).ok().and_then(|u| u.host_str().map(|h| h.to_string())) else {
+        return;
+    };
+
+    let key = format!("{}:{}", paths::SOFT_404, host);
+    let mut pipeline = pipe();
+    pipeline.cmd("HINCRBY").arg(&key).arg("detected").arg(1).ignore();
+    pipeline.cmd("HINCRBY").arg(&key).arg(reason).arg(1).ignore();
+
+    let mut kv_conn = get_kv_conn();
+    let _: RedisResult<()> = pipeline.query(&mut *kv_conn);
+}
+
+/// Fingerprint of the host's error page, probed once per SOFT404_PROBE_TTL_SECS and cached
+async fn error_template(url: &Url) -> Option<u64> {
+    let origin = url.origin().ascii_serialization();
+    let cache_key = format!("{}:{}", paths::SOFT_404_PROBE, origin);
+
+    // Claim the probe; whoever loses uses the cached result (or nothing while it is pending)
+    {
+        let mut kv_conn = get_kv_conn();
+        let claimed: Option<String> = cmd("SET")
+            .arg(&cache_key)
+            .arg(PROBING)
+            .arg("NX")
+            .arg("EX")
+            .arg(120)
+            .query(&mut *kv_conn)
+            .unwrap_or(None);
+        if claimed.is_none() {
+            let cached: Option<String> = cmd("GET").arg(&cache_key).query(&mut *kv_conn).unwrap_or(None);
+            return match cached.as_deref() {
+                None | Some(PROBING) => None,
+                Some(cached) => u64::from_str_radix(cached, 16).ok(),
+            };
+        }
+    }
+
+    let template = probe(url, &origin).await;
+    let value = template.map_or_else(|| NO_TEMPLATE.to_string(), |fp| format!("{:016x}", fp));
+    let mut kv_conn = get_kv_conn();
+    let _: RedisResult<()> = cmd("SET")
+        .arg(&cache_key)
+        .arg(&value)
+        .arg("EX")
+        .arg(SOFT404_PROBE_TTL_SECS)
+        .query(&mut *kv_conn);
+    println!("🕳️ Soft-404 probe {}: {}", origin, value);
+    template
+}
+
+/// Fetches a path that cannot exist. A 200 (without redirects) is the host's soft-404 template.
+async fn probe(url: &Url, origin: &str) -> Option<u64> {
+    let random: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(24)
+        .map(char::from)
+        .collect();
+    let probe_url = format!("{}/{}-{}", origin, random.to_lowercase(), CRAWLER_TOKEN.to_lowercase());
+    let parsed = Url::parse(&probe_url).ok()?;
+
+    let rules = robots::rules_for(&parsed).await;
+    if rules.disallow_reason(&CRAWLER_TOKEN, &parsed).is_some() {
+        return None;
+    }
+    politeness::wait_for_slot(url.host_str().unwrap_or_default(), politeness::host_delay_ms(&rules)).await;
+
+    let response = FETCHER.fetch(&probe_url, &Validators::default()).await.ok()?;
+    if !response.redirects.is_empty() {
+        return None;
+    }
+    let Ok(CrawlOutcome::Fetched(res)) = parse_page(&response) else {
+        return None;
+    };
+    neardup::simhash_min(res.metadata.cleaned_text.as_deref()?, 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(title: &str, text: &str) -> PageMetadata {
+        PageMetadata {
+            title: Some(title.to_string()),
+            cleaned_text: Some(text.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn error_titles_and_texts_are_recognised() {
+        assert_eq!(heuristic(&page("404 - Fixture Lab", "Sorry.")), Some("title"));
+        assert_eq!(heuristic(&page("Fixture Lab | Page Not Found", "Sorry.")), Some("title"));
+        assert_eq!(
+            heuristic(&page("Fixture Lab", "Oops! The page you are looking for does not exist.")),
+            Some("text")
+        );
+        assert_eq!(heuristic(&page("Search", "Your search did not match any documents.")), Some("text"));
+    }
+
+    #[test]
+    fn real_pages_are_not_soft_404s() {
+        assert_eq!(heuristic(&page("Crawling at Scale", "We crawl the web politely.")), None);
+
+        // Long pages may mention the wording without being error pages
+        let article = format!("{} Page not found errors are common. ", "Crawlers revisit pages. ".repeat(200));
+        assert!(article.chars().count() > SOFT404_MAX_TEXT);
+        assert_eq!(heuristic(&page("Page not found: a study of broken links", &article)), None);
+    }
+}
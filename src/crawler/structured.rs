@@ -0,0 +1,283 @@
+use scraper::{ElementRef, Html, Selector};
+use serde_json::{Map, Value};
+use url::Url;
+
+use crate::crawler::scholarly::normalize_doi;
+
+/// Entities kept per page; listing pages can embed hundreds
+const MAX_ENTITIES: usize = 16;
+
+/// A schema.org entity embedded in a page as JSON-LD or microdata
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Entity {
+    pub types: Vec<String>,   // short names, e.g. `ScholarlyArticle`, `Dataset`
+    pub name: Option<String>, // `name`, else `headline`
+    pub authors: Vec<String>, // `author` / `creator` names
+    pub date_published: Option<String>,
+    pub date_modified: Option<String>,
+    pub identifiers: Vec<String>, // `identifier` / `doi` / `isbn` / `issn`, DOIs bare
+}
+
+/// Typed entities from `<script type="application/ld+json">` blocks and top-level microdata items
+pub fn extract(document: &Html, page_url: &str) -> Vec<Entity> {
+    let mut items = Vec::new();
+
+    for script in document.select(&Selector::parse("script[type]").unwrap()) {
+        let script_type = script.attr("type").unwrap_or_default().to_lowercase();
+        if !script_type.contains("ld+json") {
+            continue;
+        }
+        let raw = script.text().collect::<String>();
+        let raw = raw.trim().trim_start_matches("<!--").trim_end_matches("-->");
+        // Malformed blocks are common and just skipped
+        if let Ok(json) = serde_json::from_str::<Value>(raw) {
+            flatten(json, &mut items);
+        }
+    }
+
+    let base = Url::parse(page_url).ok();
+    for item in document.select(&Selector::parse("[itemscope][itemtype]").unwrap()) {
+        if item.attr("itemprop").is_none() {
+            items.push(microdata_item(item, &base));
+        }
+    }
+
+    items.iter().filter_map(entity).take(MAX_ENTITIES).collect()
+}
+
+/// Top-level items, `@graph` members and `mainEntity` values
+fn flatten(json: Value, items: &mut Vec<Value>) {
+    match json {
+        Value::Array(values) => values.into_iter().for_each(|value| flatten(value, items)),
+        Value::Object(mut object) => {
+            if let Some(graph) = object.remove("@graph") {
+                flatten(graph, items);
+            }
+            if let Some(main) = object.get("mainEntity").cloned() {
+                flatten(main, items);
+            }
+            if object.contains_key("@type") {
+                items.push(Value::Object(object));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn entity(item: &Value) -> Option<Entity> {
+    let types: Vec<String> = values(&item["@type"]).filter_map(text).map(|t| short_type(&t)).collect();
+    if types.is_empty() {
+        return None;
+    }
+
+    let authors = ["author", "creator"]
+        .iter()
+        .flat_map(|key| values(&item[*key]))
+        .filter_map(person_name)
+        .fold(Vec::new(), |mut authors, name| {
+            if !authors.contains(&name) {
+                authors.push(name);
+            }
+            authors
+        });
+
+    let identifiers = ["identifier", "doi", "isbn", "issn"]
+        .iter()
+        .flat_map(|key| values(&item[*key]))
+        .filter_map(|id| text(&id["value"]).or_else(|| text(id)))
+        .map(|id| normalize_doi(&id).unwrap_or(id))
+        .fold(Vec::new(), |mut ids, id| {
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+            ids
+        });
+
+    Some(Entity {
+        types,
+        name: first_text(&item["name"]).or_else(|| first_text(&item["headline"])),
+        authors,
+        date_published: first_text(&item["datePublished"]).or_else(|| first_text(&item["dateCreated"])),
+        date_modified: first_text(&item["dateModified"]),
+        identifiers,
+    })
+}
+
+/// A property's values: arrays as their members, anything else as itself
+fn values(value: &Value) -> impl Iterator<Item = &Value> {
+    let values: Vec<&Value> = match value {
+        Value::Array(members) => members.iter().collect(),
+        Value::Null => Vec::new(),
+        single => vec![single],
+    };
+    values.into_iter()
+}
+
+/// A plain string, number or `{"@value": ..}`
+fn text(value: &Value) -> Option<String> {
+    let text = match value {
+        Value::String(s) => s.trim().to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::Object(object) => return object.get("@value").and_then(text),
+        _ => return None,
+    };
+    (!text.is_empty()).then_some(text)
+}
+
+fn first_text(value: &Value) -> Option<String> {
+    values(value).find_map(text)
+}
+
+/// `"Ada Lovelace"`, or a Person / Organization with `name` (or `givenName` + `familyName`)
+fn person_name(person: &Value) -> Option<String> {
+    if !person.is_object() {
+        return text(person);
+    }
+    first_text(&person["name"]).or_else(|| {
+        let full = [first_text(&person["givenName"]), first_text(&person["familyName"])]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" ");
+        (!full.is_empty()).then_some(full)
+    })
+}
+
+/// `http://schema.org/Dataset`, `schema:Dataset` -> `Dataset`
+fn short_type(schema_type: &str) -> String {
+    schema_type
+        .rsplit(['/', '#', ':'])
+        .next()
+        .unwrap_or(schema_type)
+        .to_string()
+}
+
+/// A microdata item as the JSON-LD object it would have been
+fn microdata_item(item: ElementRef, base: &Option<Url>) -> Value {
+    let mut object = Map::new();
+    let types: Vec<Value> = item
+        .attr("itemtype")
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(|t| Value::String(t.to_string()))
+        .collect();
+    object.insert("@type".to_string(), Value::Array(types));
+    collect_props(item, &mut object, base);
+    Value::Object(object)
+}
+
+/// Properties of the item `element` belongs to; nested items are values, not property sources
+fn collect_props(element: ElementRef, object: &mut Map<String, Value>, base: &Option<Url>) {
+    for child in element.children().filter_map(ElementRef::wrap) {
+        let nested = child.attr("itemscope").is_some();
+        if let Some(names) = child.attr("itemprop") {
+            let value = if nested {
+                microdata_item(child, base)
+            } else {
+                Value::String(prop_value(child, base))
+            };
+            for name in names.split_whitespace() {
+                match object.get_mut(name) {
+                    Some(Value::Array(existing)) => existing.push(value.clone()),
+                    _ => {
+                        object.insert(name.to_string(), Value::Array(vec![value.clone()]));
+                    }
+                }
+            }
+        }
+        if !nested {
+            collect_props(child, object, base);
+        }
+    }
+}
+
+/// The value of a non-item property element, per the microdata spec
+fn prop_value(element: ElementRef, base: &Option<Url>) -> String {
+    let element_name = element.value().name();
+    let attribute = match element_name {
+        "meta" => Some("content"),
+        "a" | "area" | "link" => Some("href"),
+        "img" | "audio" | "video" | "source" | "embed" | "iframe" => Some("src"),
+        "object" => Some("data"),
+        "time" => Some("datetime"),
+        "data" | "meter" => Some("value"),
+        _ => None,
+    };
+    let Some(attribute) = attribute else {
+        return element.text().collect::<Vec<_>>().join(" ").split_whitespace().collect::<Vec<_>>().join(" ");
+    };
+
+    let value = element.attr(attribute).unwrap_or_default().trim();
+    match (attribute, base) {
+        ("href" | "src" | "data", Some(base)) => base.join(value).map(|url| url.to_string()).unwrap_or_default(),
+        ("datetime", _) if value.is_empty() => element.text().collect::<String>().trim().to_string(),
+        _ => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entities(html: &str) -> Vec<Entity> {
+        extract(&Html::parse_document(html), "https://fixture.test/datasets/1")
+    }
+
+    #[test]
+    fn json_ld_graphs_and_main_entities_are_flattened() {
+        let html = r#"<script type="application/ld+json">
+            {"@context": "https://schema.org", "@graph": [
+                {"@type": "WebPage", "name": "Datasets", "mainEntity": {
+                    "@type": "Dataset",
+                    "name": "Crawl Latency Measurements",
+                    "creator": [{"@type": "Person", "givenName": "Ada", "familyName": "Lovelace"},
+                                {"@type": "Organization", "name": "Fixture Lab"}, "Ada Lovelace"],
+                    "datePublished": "2024-05-01",
+                    "identifier": ["https://doi.org/10.5555/fixture.dataset", {"@type": "PropertyValue", "value": "fixture-ds-1"}]
+                }}
+            ]}
+            </script>
+            <script type="application/ld+json">{ not json }</script>"#;
+        let entities = entities(html);
+        assert_eq!(entities.len(), 2);
+        assert_eq!(entities[0].types, vec!["Dataset"]);
+        assert_eq!(entities[0].authors, vec!["Ada Lovelace", "Fixture Lab"]);
+        assert_eq!(entities[0].identifiers, vec!["10.5555/fixture.dataset", "fixture-ds-1"]);
+        assert_eq!(entities[0].date_published.as_deref(), Some("2024-05-01"));
+        assert_eq!(entities[1].types, vec!["WebPage"]);
+        assert_eq!(entities[1].name.as_deref(), Some("Datasets"));
+    }
+
+    #[test]
+    fn microdata_items_become_entities() {
+        let html = r#"<div itemscope itemtype="https://schema.org/SoftwareSourceCode">
+              <h2 itemprop="name">latency-probe</h2>
+              <span itemprop="author" itemscope itemtype="https://schema.org/Person">
+                <span itemprop="name">Alan Turing</span>
+              </span>
+              <time itemprop="datePublished" datetime="2024-04-20">April 20</time>
+              <time itemprop="dateModified">2024-06-15</time>
+            </div>"#;
+        assert_eq!(
+            entities(html),
+            vec![Entity {
+                types: vec!["SoftwareSourceCode".to_string()],
+                name: Some("latency-probe".to_string()),
+                authors: vec!["Alan Turing".to_string()],
+                date_published: Some("2024-04-20".to_string()),
+                date_modified: Some("2024-06-15".to_string()),
+                identifiers: Vec::new(),
+            }]
+        );
+    }
+
+    #[test]
+    fn types_are_shortened_and_untyped_items_skipped() {
+        assert_eq!(short_type("http://schema.org/Dataset"), "Dataset");
+        assert_eq!(short_type("schema:ScholarlyArticle"), "ScholarlyArticle");
+        let html = r#"<script type="application/ld+json">[{"name": "untyped"}, {"@type": "Article", "headline": "Hi"}]</script>"#;
+        let entities = entities(html);
+        assert_eq!(entities.len(), 1);
+        assert_eq!(entities[0].name.as_deref(), Some("Hi"));
+    }
+}
@@ -1,6 +1,12 @@
+use chrono::Utc;
 use url::Url;
 use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use crate::crawler::canonical;
+use crate::crawler::crawl::{PageMetadata, Validators};
 use crate::db::paths;
+use crate::common::DOMAINS_SET;
+use crate::common::config::VALIDATORS_TTL_SECS;
 
 use crate::db::get_kv_conn;
 use r2d2_redis::redis::{ pipe, cmd, RedisResult};
@@ -26,6 +32,24 @@ pub fn clean_url(url: &str) -> Option<String> {
     Some(parsed.to_string())
 }
 
+/// Whether the url's host is one of DOMAINS_SET or a subdomain of one
+pub fn in_scope(url: &str) -> bool {
+    let Some(host) = Url::parse(url).ok().and_then(|u| u.host_str().map(|h| h.to_lowercase())) else {
+        return false;
+    };
+    let host = host.strip_prefix("www.").unwrap_or(&host);
+    DOMAINS_SET
+        .iter()
+        .any(|domain| host == *domain || host.ends_with(&format!(".{}", domain)))
+}
+
+/// Key used for a url in the `crawl_seen` bloom filter
+pub fn url_hash(url: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(url.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
 pub fn hash_links(
     links: &Vec<String>,
 ) -> RedisResult<Vec<(String, String)>> {
@@ -36,11 +60,7 @@ pub fn hash_links(
             continue;
         }
 
-        let mut hasher = Sha1::new();
-        hasher.update(link.as_bytes());
-        let key = format!("{:x}", hasher.finalize());
-
-        hashed_links.push((link.clone(), key));
+        hashed_links.push((link.clone(), url_hash(link)));
     }
 
     Ok(hashed_links)
@@ -48,7 +68,7 @@ pub fn hash_links(
 
 
 
-pub async fn back_link_score(url: &str, backlinks: &[(String, String)]) {
+pub fn back_link_score(url: &str, backlinks: &[(String, String)]) {
     let mut kv_conn = get_kv_conn();
 
     // These are the *Bloom filters* themselves
@@ -62,7 +82,9 @@ pub async fn back_link_score(url: &str, backlinks: &[(String, String)]) {
     // ⚡ Use a pipeline for better performance
     let mut pipeline = pipe();
 
-    for (backlink_url, _) in backlinks {
+    // Links to a known non-canonical variant count for its canonical
+    let backlink_urls: Vec<String> = backlinks.iter().map(|(link, _)| link.clone()).collect();
+    for backlink_url in canonical::resolve_many(&backlink_urls) {
         let backlink_url = backlink_url.trim();
         if backlink_url.is_empty() {
             continue;
@@ -148,3 +170,117 @@ pub async fn back_link_score(url: &str, backlinks: &[(String, String)]) {
         println!("✅ Finished backlink + domain scoring for {}", url);
     }
 }
+
+
+/// Remembers why a url was not fetched (robots.txt, ...) in the `skp` hash
+pub fn record_skip(url: &str, reason: &str) {
+    let mut kv_conn = get_kv_conn();
+    let _: RedisResult<()> = cmd("HSET")
+        .arg(paths::SKIPPED)
+        .arg(url)
+        .arg(reason)
+        .query(&mut *kv_conn);
+}
+
+/// ETag / Last-Modified stored from the last successful crawl of `url`
+pub fn load_validators(url: &str) -> Validators {
+    let mut kv_conn = get_kv_conn();
+    let fields: HashMap<String, String> = cmd("HGETALL")
+        .arg(format!("{}:{}", paths::VALIDATORS, url))
+        .query(&mut *kv_conn)
+        .unwrap_or_default();
+
+    Validators {
+        etag: fields.get("etag").cloned(),
+        last_modified: fields.get("last_modified").cloned(),
+    }
+}
+
+/// Keyed by the url the conditional request will be sent to, not the final / canonical one
+pub fn store_validators(requested_url: &str, metadata: &PageMetadata) {
+    let key = format!("{}:{}", paths::VALIDATORS, requested_url);
+    let mut kv_conn = get_kv_conn();
+    let mut pipeline = pipe();
+
+    // Replace whatever the previous crawl left behind
+    pipeline.cmd("DEL").arg(&key);
+    pipeline.cmd("HSET").arg(&key).arg("crawled_at").arg(metadata.crawl_timestamp);
+    if let Some(etag) = &metadata.etag {
+        pipeline.cmd("HSET").arg(&key).arg("etag").arg(etag);
+    }
+    if let Some(last_modified) = &metadata.last_modified {
+        pipeline.cmd("HSET").arg(&key).arg("last_modified").arg(last_modified);
+    }
+    if let Some(user_agent) = &metadata.user_agent {
+        pipeline.cmd("HSET").arg(&key).arg("user_agent").arg(user_agent);
+    }
+    pipeline.cmd("EXPIRE").arg(&key).arg(VALIDATORS_TTL_SECS);
+
+    if let Err(err) = pipeline.query::<()>(&mut *kv_conn) {
+        eprintln!("⚠️ Failed to store validators for {}: {}", requested_url, err);
+    }
+}
+
+/// Claims `document_url` (a final or canonical url) for the crawl of `requested_url`; the first
+/// claim wins. Returns whether `requested_url` owns the document: the owner's recrawls keep
+/// refreshing it, any other url that lands on it is a duplicate.
+pub fn claim_document(document_url: &str, requested_url: &str) -> bool {
+    let key = format!("{}:{}", paths::DOCUMENT_OWNER, document_url);
+    let mut kv_conn = get_kv_conn();
+    let claimed: RedisResult<(Option<String>, Option<String>)> = pipe()
+        .cmd("SET")
+        .arg(&key)
+        .arg(requested_url)
+        .arg("NX")
+        .arg("EX")
+        .arg(VALIDATORS_TTL_SECS)
+        .cmd("GET")
+        .arg(&key)
+        .query(&mut *kv_conn);
+
+    match claimed {
+        Ok((_, owner)) => {
+            let owned = owner.is_none_or(|owner| owner == requested_url);
+            if owned {
+                let _: RedisResult<()> = cmd("EXPIRE").arg(&key).arg(VALIDATORS_TTL_SECS).query(&mut *kv_conn);
+            }
+            owned
+        }
+        Err(err) => {
+            eprintln!("⚠️ Failed to claim {}: {}", document_url, err);
+            true
+        }
+    }
+}
+
+/// Last crawl time (unix seconds) of each url, `None` if never crawled
+pub fn last_crawled_many(urls: &[String]) -> Vec<Option<i64>> {
+    if urls.is_empty() {
+        return Vec::new();
+    }
+
+    let mut kv_conn = get_kv_conn();
+    let mut pipeline = pipe();
+    for url in urls {
+        pipeline.cmd("HGET").arg(format!("{}:{}", paths::VALIDATORS, url)).arg("crawled_at");
+    }
+    pipeline
+        .query::<Vec<Option<i64>>>(&mut *kv_conn)
+        .unwrap_or_else(|_| vec![None; urls.len()])
+}
+
+/// Refreshes the crawl timestamp of an unchanged (304) page
+pub fn touch_crawled(url: &str) {
+    let key = format!("{}:{}", paths::VALIDATORS, url);
+    let mut kv_conn = get_kv_conn();
+    let mut pipeline = pipe();
+    pipeline.cmd("HSET").arg(&key).arg("crawled_at").arg(Utc::now().timestamp());
+    pipeline.cmd("EXPIRE").arg(&key).arg(VALIDATORS_TTL_SECS);
+    let _: RedisResult<()> = pipeline.query(&mut *kv_conn);
+}
+
+/// Counts pages cut at MAX_BODY_BYTES (`stats:truncated`)
+pub fn count_truncated() {
+    let mut kv_conn = get_kv_conn();
+    let _: RedisResult<()> = cmd("INCR").arg(paths::STATS_TRUNCATED).query(&mut *kv_conn);
+}
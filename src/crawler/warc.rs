@@ -0,0 +1,577 @@
+use chrono::{SecondsFormat, Utc};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use once_cell::sync::Lazy;
+use r2d2_redis::redis::{RedisResult, cmd};
+use rand::Rng;
+use reqwest::StatusCode;
+use reqwest::header::{CONTENT_ENCODING, CONTENT_LENGTH, HeaderMap, HeaderName, HeaderValue, TRANSFER_ENCODING};
+use sha1::{Digest, Sha1};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use url::Url;
+
+use crate::common::config::{WARC_DIR, WARC_MAX_FILE_BYTES};
+use crate::crawler::clean_url;
+use crate::crawler::fetch::FetchResponse;
+use crate::db::{get_kv_conn, paths};
+
+/// The open WARC file; `None` when archiving is off (no WARC_DIR)
+static WRITER: Lazy<Option<Mutex<WarcWriter>>> =
+    Lazy::new(|| WARC_DIR.as_ref().map(|dir| Mutex::new(WarcWriter::new(PathBuf::from(dir)))));
+
+/// Appends gzip-per-record WARC/1.1 files, rotating at WARC_MAX_FILE_BYTES
+struct WarcWriter {
+    dir: PathBuf,
+    file: Option<(File, String)>, // open file and its name
+    written: u64,
+    seq: u32,
+}
+
+/// Where a record lives: file name, byte offset and compressed length of its gzip member
+struct RecordLocation {
+    file: String,
+    offset: u64,
+    length: u64,
+}
+
+/// Archives the request and response of one fetch when WARC_DIR is set, and indexes it
+/// under `wi:<url>` (score = capture time). Failures are logged, never fatal to the crawl.
+pub fn archive(response: &FetchResponse) {
+    let Some(writer) = WRITER.as_ref() else { return };
+
+    // Nothing new to keep in a 304
+    if response.status == 304 {
+        return;
+    }
+
+    let captured_at = Utc::now();
+    let location = {
+        let mut writer = writer.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        writer.write_exchange(response, &captured_at.to_rfc3339_opts(SecondsFormat::Secs, true))
+    };
+
+    match location {
+        Ok(location) => {
+            // Indexed under the document's identity, the record keeps the url as fetched
+            let url = clean_url(&response.url).unwrap_or_else(|| response.url.clone());
+            let mut kv_conn = get_kv_conn();
+            let _: RedisResult<()> = cmd("ZADD")
+                .arg(format!("{}:{}", paths::WARC_INDEX, url))
+                .arg(captured_at.timestamp())
+                .arg(format!("{}:{}:{}", location.file, location.offset, location.length))
+                .query(&mut *kv_conn);
+        }
+        Err(err) => eprintln!("⚠️ WARC write failed for {}: {}", response.url, err),
+    }
+}
+
+impl WarcWriter {
+    fn new(dir: PathBuf) -> Self {
+        WarcWriter {
+            dir,
+            file: None,
+            written: 0,
+            seq: 0,
+        }
+    }
+
+    /// request + response (+ metadata with the redirect chain); returns the response record
+    fn write_exchange(&mut self, response: &FetchResponse, date: &str) -> io::Result<RecordLocation> {
+        if self.file.is_none() || self.written >= *WARC_MAX_FILE_BYTES {
+            self.rotate(date)?;
+        }
+
+        let response_id = record_id();
+        let request_id = record_id();
+
+        let mut request_block = request_head(&response.url, &response.request_headers).into_bytes();
+        request_block.extend_from_slice(b"\r\n");
+
+        let reason = StatusCode::from_u16(response.status)
+            .ok()
+            .and_then(|s| s.canonical_reason())
+            .unwrap_or("");
+        let mut response_block = format!("HTTP/1.1 {} {}\r\n", response.status, reason).into_bytes();
+        response_block.extend_from_slice(&header_lines(&payload_headers(&response.headers, response.body.len())));
+        response_block.extend_from_slice(b"\r\n");
+        response_block.extend_from_slice(&response.body);
+
+        let mut response_fields = vec![
+            ("WARC-Type", "response".to_string()),
+            ("WARC-Record-ID", response_id.clone()),
+            ("WARC-Date", date.to_string()),
+            ("WARC-Target-URI", response.url.clone()),
+            ("WARC-Payload-Digest", format!("sha1:{}", base32(&Sha1::digest(&response.body)))),
+            ("Content-Type", "application/http;msgtype=response".to_string()),
+        ];
+        if response.truncated {
+            response_fields.push(("WARC-Truncated", "length".to_string()));
+        }
+        let location = self.append(&response_fields, &response_block)?;
+
+        let request_fields = vec![
+            ("WARC-Type", "request".to_string()),
+            ("WARC-Record-ID", request_id),
+            ("WARC-Date", date.to_string()),
+            ("WARC-Target-URI", response.url.clone()),
+            ("WARC-Concurrent-To", response_id.clone()),
+            ("Content-Type", "application/http;msgtype=request".to_string()),
+        ];
+        self.append(&request_fields, &request_block)?;
+
+        // How we got here: the redirect hops before the archived url
+        if !response.redirects.is_empty() {
+            let hops: String = response
+                .redirects
+                .iter()
+                .map(|hop| format!("via: {} {} {}\r\n", hop.status, hop.url, hop.location))
+                .collect();
+            let metadata_fields = vec![
+                ("WARC-Type", "metadata".to_string()),
+                ("WARC-Record-ID", record_id()),
+                ("WARC-Date", date.to_string()),
+                ("WARC-Target-URI", response.url.clone()),
+                ("WARC-Refers-To", response_id),
+                ("Content-Type", "application/warc-fields".to_string()),
+            ];
+            self.append(&metadata_fields, hops.as_bytes())?;
+        }
+
+        Ok(location)
+    }
+
+    /// Starts a new file that begins with a warcinfo record
+    fn rotate(&mut self, date: &str) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        self.seq += 1;
+        let name = format!(
+            "research-{}-{:05}-{}.warc.gz",
+            Utc::now().format("%Y%m%d%H%M%S"),
+            self.seq,
+            std::process::id()
+        );
+        let file = OpenOptions::new().create(true).append(true).open(self.dir.join(&name))?;
+        println!("🗄️ Writing WARC {}", name);
+
+        self.file = Some((file, name.clone()));
+        self.written = 0;
+
+        let info = format!(
+            "software: research/{}\r\nformat: WARC File Format 1.1\r\nconformsTo: http://iipc.github.io/warc-specifications/specifications/warc-format/warc-1.1/\r\n",
+            env!("CARGO_PKG_VERSION")
+        );
+        let fields = vec![
+            ("WARC-Type", "warcinfo".to_string()),
+            ("WARC-Record-ID", record_id()),
+            ("WARC-Date", date.to_string()),
+            ("WARC-Filename", name),
+            ("Content-Type", "application/warc-fields".to_string()),
+        ];
+        self.append(&fields, info.as_bytes())?;
+        Ok(())
+    }
+
+    /// Writes one record as its own gzip member, so it can be read back from its offset alone
+    fn append(&mut self, fields: &[(&str, String)], block: &[u8]) -> io::Result<RecordLocation> {
+        let mut record = b"WARC/1.1\r\n".to_vec();
+        for (name, value) in fields {
+            record.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+        }
+        record.extend_from_slice(format!("Content-Length: {}\r\n\r\n", block.len()).as_bytes());
+        record.extend_from_slice(block);
+        record.extend_from_slice(b"\r\n\r\n");
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&record)?;
+        let compressed = encoder.finish()?;
+
+        let (file, name) = self.file.as_mut().ok_or_else(|| io::Error::other("no open WARC file"))?;
+        file.write_all(&compressed)?;
+
+        let location = RecordLocation {
+            file: name.clone(),
+            offset: self.written,
+            length: compressed.len() as u64,
+        };
+        self.written += compressed.len() as u64;
+        Ok(location)
+    }
+}
+
+/// The request line and headers we sent for the final url
+fn request_head(url: &str, headers: &HeaderMap) -> String {
+    let parsed = Url::parse(url).ok();
+    let target = parsed
+        .as_ref()
+        .map(|u| match u.query() {
+            Some(query) => format!("{}?{}", u.path(), query),
+            None => u.path().to_string(),
+        })
+        .unwrap_or_else(|| "/".to_string());
+    let host = parsed
+        .as_ref()
+        .and_then(|u| u.host_str().map(|h| h.to_string()))
+        .unwrap_or_default();
+
+    let mut head = format!("GET {} HTTP/1.1\r\nHost: {}\r\n", target, host);
+    head.push_str(&String::from_utf8_lossy(&header_lines(headers)));
+    head
+}
+
+/// Response headers that describe the stored body: reqwest has already undone chunking and
+/// content coding, so those headers go and Content-Length is the decoded length
+fn payload_headers(headers: &HeaderMap, body_len: usize) -> HeaderMap {
+    let mut headers = headers.clone();
+    headers.remove(TRANSFER_ENCODING);
+    headers.remove(CONTENT_ENCODING);
+    headers.insert(CONTENT_LENGTH, HeaderValue::from(body_len));
+    headers
+}
+
+fn header_lines(headers: &HeaderMap) -> Vec<u8> {
+    let mut lines = Vec::new();
+    for (name, value) in headers {
+        lines.extend_from_slice(name.as_str().as_bytes());
+        lines.extend_from_slice(b": ");
+        lines.extend_from_slice(value.as_bytes());
+        lines.extend_from_slice(b"\r\n");
+    }
+    lines
+}
+
+/// `<urn:uuid:...>` with a random (v4) uuid
+fn record_id() -> String {
+    let mut bytes: [u8; 16] = rand::thread_rng().r#gen();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "<urn:uuid:{}-{}-{}-{}-{}>",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// RFC 4648 base32, the customary encoding of WARC sha1 digests
+fn base32(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let mut out = String::new();
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            out.push(ALPHABET[((buffer >> (bits - 5)) & 31) as usize] as char);
+            bits -= 5;
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    if bits > 0 {
+        out.push(ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    out
+}
+
+/// One record read back from a WARC file
+#[derive(Debug)]
+pub struct WarcRecord {
+    pub offset: u64,      // where the record (its gzip member) starts in the file
+    pub next_offset: u64, // where reading resumes after it
+    pub fields: HashMap<String, String>, // WARC header fields, lowercased names
+    pub block: Vec<u8>,
+}
+
+impl WarcRecord {
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(|v| v.as_str())
+    }
+
+    pub fn warc_type(&self) -> &str {
+        self.field("warc-type").unwrap_or_default()
+    }
+
+    /// WARC/1.0 writers sometimes wrap the uri in `<>`
+    pub fn target_uri(&self) -> Option<&str> {
+        self.field("warc-target-uri")
+            .map(|uri| uri.trim_start_matches('<').trim_end_matches('>'))
+    }
+
+    /// The HTTP response of a `response` record, shaped like a fresh fetch
+    pub fn to_fetch_response(&self) -> Option<FetchResponse> {
+        if self.warc_type() != "response" {
+            return None;
+        }
+
+        let head_end = self.block.windows(4).position(|w| w == b"\r\n\r\n").map(|p| (p, p + 4)).or_else(|| {
+            self.block.windows(2).position(|w| w == b"\n\n").map(|p| (p, p + 2))
+        })?;
+        let head = String::from_utf8_lossy(&self.block[..head_end.0]);
+        let mut lines = head.lines();
+
+        let status: u16 = lines.next()?.split_whitespace().nth(1)?.parse().ok()?;
+        let mut headers = HeaderMap::new();
+        for line in lines {
+            let Some((name, value)) = line.split_once(':') else { continue };
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.trim().as_bytes()),
+                HeaderValue::from_str(value.trim()),
+            ) {
+                headers.append(name, value);
+            }
+        }
+
+        let body = decode_payload(&mut headers, self.block[head_end.1..].to_vec())?;
+
+        Some(FetchResponse {
+            url: self.target_uri()?.to_string(),
+            request_headers: HeaderMap::new(),
+            redirects: Vec::new(),
+            status,
+            headers,
+            body,
+            truncated: self.field("warc-truncated").is_some(),
+        })
+    }
+}
+
+/// Archives written by other tools (wget, Heritrix) keep the payload as it came off the wire:
+/// undo chunking and gzip / deflate. `None` for a content coding we can't decode.
+fn decode_payload(headers: &mut HeaderMap, body: Vec<u8>) -> Option<Vec<u8>> {
+    let header = |headers: &HeaderMap, name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_lowercase())
+            .unwrap_or_default()
+    };
+
+    let mut body = body;
+    if header(headers, "transfer-encoding").contains("chunked") {
+        body = dechunk(&body).unwrap_or(body);
+        headers.remove("transfer-encoding");
+    }
+
+    let coding = header(headers, "content-encoding");
+    body = match coding.as_str() {
+        "" | "identity" => return Some(body),
+        "gzip" | "x-gzip" => {
+            let mut decoded = Vec::new();
+            flate2::read::MultiGzDecoder::new(&body[..]).read_to_end(&mut decoded).ok()?;
+            decoded
+        }
+        "deflate" => {
+            // zlib-wrapped per the spec, raw deflate in practice often enough
+            let mut decoded = Vec::new();
+            if flate2::read::ZlibDecoder::new(&body[..]).read_to_end(&mut decoded).is_err() {
+                decoded.clear();
+                flate2::read::DeflateDecoder::new(&body[..]).read_to_end(&mut decoded).ok()?;
+            }
+            decoded
+        }
+        _ => return None,
+    };
+    headers.remove("content-encoding");
+    headers.remove("content-length");
+    Some(body)
+}
+
+/// Chunked transfer coding -> plain body (`None` if malformed)
+fn dechunk(body: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut rest = body;
+    loop {
+        let line_end = rest.windows(2).position(|w| w == b"\r\n")?;
+        let size_field = String::from_utf8_lossy(&rest[..line_end]);
+        let size = usize::from_str_radix(size_field.split(';').next()?.trim(), 16).ok()?;
+        rest = &rest[line_end + 2..];
+        if size == 0 {
+            return Some(out);
+        }
+        out.extend_from_slice(rest.get(..size)?);
+        rest = rest.get(size + 2..)?;
+    }
+}
+
+/// Reads the records of a `.warc` or `.warc.gz` file in order, starting at a record offset
+pub struct WarcReader {
+    reader: BufReader<File>,
+    gzip: bool,
+    pending: VecDeque<WarcRecord>,
+}
+
+impl WarcReader {
+    pub fn open(path: &Path, offset: u64) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        reader.seek(SeekFrom::Start(offset))?;
+        let gzip = reader.fill_buf()?.starts_with(&[0x1f, 0x8b]);
+        Ok(WarcReader {
+            reader,
+            gzip,
+            pending: VecDeque::new(),
+        })
+    }
+
+    fn read_next(&mut self) -> io::Result<Option<WarcRecord>> {
+        if !self.gzip {
+            let offset = self.reader.stream_position()?;
+            return Ok(read_raw_record(&mut self.reader)?.map(|(fields, block)| WarcRecord {
+                offset,
+                next_offset: self.reader.stream_position().unwrap_or(offset),
+                fields,
+                block,
+            }));
+        }
+
+        // Normally one gzip member per record; a member holding several is resumed from its start
+        while self.pending.is_empty() {
+            let start = self.reader.stream_position()?;
+            if self.reader.fill_buf()?.is_empty() {
+                return Ok(None);
+            }
+            let mut data = Vec::new();
+            flate2::bufread::GzDecoder::new(&mut self.reader).read_to_end(&mut data)?;
+            let end = self.reader.stream_position()?;
+
+            let mut cursor = Cursor::new(&data[..]);
+            while let Some((fields, block)) = read_raw_record(&mut cursor)? {
+                self.pending.push_back(WarcRecord {
+                    offset: start,
+                    next_offset: start,
+                    fields,
+                    block,
+                });
+            }
+            if let Some(last) = self.pending.back_mut() {
+                last.next_offset = end;
+            }
+        }
+        Ok(self.pending.pop_front())
+    }
+}
+
+impl Iterator for WarcReader {
+    type Item = io::Result<WarcRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_next().transpose()
+    }
+}
+
+/// `.warc` / `.warc.gz` files in a directory, in name order (for our writer: capture order)
+pub fn list_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            let name = path.to_string_lossy();
+            name.ends_with(".warc") || name.ends_with(".warc.gz")
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// WARC header fields (lowercased names) and content block
+type RawRecord = (HashMap<String, String>, Vec<u8>);
+
+/// The next record, `None` at the end of the input
+fn read_raw_record(reader: &mut impl BufRead) -> io::Result<Option<RawRecord>> {
+    // Skip the blank lines that close the previous record
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if !line.trim().is_empty() {
+            break;
+        }
+    }
+    if !line.starts_with("WARC/") {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a WARC record"));
+    }
+
+    let mut fields = HashMap::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.trim_end().split_once(':') {
+            fields.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let length: u64 = fields
+        .get("content-length")
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "WARC record without Content-Length"))?;
+    let mut block = Vec::new();
+    reader.take(length).read_to_end(&mut block)?;
+
+    Ok(Some((fields, block)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crawler::fetch::RedirectHop;
+
+    #[test]
+    fn written_records_read_back() {
+        let dir = std::env::temp_dir().join(format!("research-warc-test-{}", std::process::id()));
+        let mut writer = WarcWriter::new(dir.clone());
+
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", HeaderValue::from_static("text/html; charset=utf-8"));
+        headers.insert("etag", HeaderValue::from_static("\"v1\""));
+        // As received: reqwest decoded the body these describe
+        headers.insert("content-encoding", HeaderValue::from_static("gzip"));
+        headers.insert("transfer-encoding", HeaderValue::from_static("chunked"));
+        headers.insert("content-length", HeaderValue::from_static("12"));
+        let response = FetchResponse {
+            url: "https://example.org/papers/1?lang=en".to_string(),
+            request_headers: HeaderMap::new(),
+            redirects: vec![RedirectHop {
+                url: "http://example.org/papers/1".to_string(),
+                status: 301,
+                location: "https://example.org/papers/1?lang=en".to_string(),
+            }],
+            status: 200,
+            headers,
+            body: b"<html><body>\r\n\r\nhello</body></html>".to_vec(),
+            truncated: true,
+        };
+        let location = writer.write_exchange(&response, "2024-05-01T12:00:00Z").unwrap();
+        let path = dir.join(&location.file);
+
+        let records: Vec<WarcRecord> = WarcReader::open(&path, 0).unwrap().map(|r| r.unwrap()).collect();
+        let types: Vec<&str> = records.iter().map(|r| r.warc_type()).collect();
+        assert_eq!(types, vec!["warcinfo", "response", "request", "metadata"]);
+        assert_eq!(records[1].offset, location.offset);
+        assert_eq!(records[1].next_offset, location.offset + location.length);
+        assert_eq!(records[3].block, b"via: 301 http://example.org/papers/1 https://example.org/papers/1?lang=en\r\n");
+
+        // The response record alone, from its indexed offset
+        let record = WarcReader::open(&path, location.offset).unwrap().next().unwrap().unwrap();
+        assert_eq!(record.field("warc-date"), Some("2024-05-01T12:00:00Z"));
+        let read = record.to_fetch_response().unwrap();
+        assert_eq!(read.url, response.url);
+        assert_eq!(read.status, 200);
+        assert_eq!(read.body, response.body);
+        assert_eq!(read.header("etag").as_deref(), Some("\"v1\""));
+        assert_eq!(read.header("content-type").as_deref(), Some("text/html; charset=utf-8"));
+        assert_eq!(read.header("content-encoding"), None);
+        assert_eq!(read.header("transfer-encoding"), None);
+        assert_eq!(read.header("content-length"), Some(response.body.len().to_string()));
+        assert!(read.truncated);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
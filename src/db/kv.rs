@@ -1,9 +1,10 @@
 use crate::db::init_db::get_kv_conn;
 use r2d2::PooledConnection;
 use r2d2_redis::{RedisConnectionManager, redis::Commands}; // 👈 REQUIRED for .get(), .set(), etc.
-use r2d2_redis::redis::{ RedisResult, cmd, ConnectionLike};
+use r2d2_redis::redis::{ RedisResult, cmd};
 // 
 
+#[allow(dead_code)]
 pub fn get(key: &str, mut conn: PooledConnection<RedisConnectionManager>)-> Option<String>{
     // let mut conn = get_kv_conn();
 
@@ -18,6 +19,7 @@ pub fn get(key: &str, mut conn: PooledConnection<RedisConnectionManager>)-> Opti
     }
 }
 
+#[allow(dead_code)]
 pub fn set(key: &str, value: &str, mut conn: PooledConnection<RedisConnectionManager>) -> bool {
 
     match conn.set::<_, _, ()>(key, value) {
@@ -32,6 +34,7 @@ pub fn set(key: &str, value: &str, mut conn: PooledConnection<RedisConnectionMan
     }
 }
 
+#[allow(dead_code)]
 pub fn write_to_kvrocks_list(list_name: &str, data: &str) -> RedisResult<()> {
     let mut conn = get_kv_conn();
 
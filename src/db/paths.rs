@@ -2,9 +2,57 @@
 // cl -> crawl_list
 // uscr -> url_score
 // dscr -> domain_score
+// rbt -> robots.txt cache (rbt:<origin>)
+// skp -> skipped urls (hash url -> reason)
+// hnx -> per-host cooldown (hnx:<host>, expires when the host may be hit again)
+// dfr -> deferred urls (zset url -> ready at, ms)
+// smp -> sitemap ingestion marker per origin (smp:<origin>, expires when due again)
+// fd -> feed registry (fd:<feed_url> hash page / added_at / last_polled / cadence_secs / parse_failures / ...)
+// fdq -> feed poll schedule (zset feed_url -> next poll at, unix secs)
+// wi -> WARC captures per url (wi:<url> zset "<file>:<offset>:<length>" -> captured at, unix secs)
+// rix -> reindex checkpoints (rix:<index> hash warc file -> next offset, or "done")
+// rixd -> documents a reindex has ingested (rixd:<index> set of document urls)
+// prot -> protection counts per domain (prot:<domain> hash pages / protected / challenge / login / denied / paywall)
+// cnl -> canonical aliases (hash variant url -> canonical url)
+// shb -> SimHash LSH buckets (shb:<band pair>:<16 bits hex> set of fingerprints)
+// shc -> SimHash clusters (hash fingerprint hex -> cluster representative url)
+// s4 -> soft-404s dropped per host (s4:<host> hash detected / template / title / text)
+// s4p -> soft-404 probe per origin (s4p:<origin> error page fingerprint hex, or "none"; expires)
+// bg -> budget usage per domain (bg:<domain> hash pages / bytes / deferred_<reason>)
+// bgd -> pages fetched per domain and day (bgd:<domain>:<YYYY-MM-DD>, expires)
+// dpt -> link depth of queued urls (dpt:<url>, expires)
+// att -> fetch attempts (hash url -> failed attempts)
+// own -> document owners (own:<document url> requested url whose crawl indexes it, expires)
+// vld -> recrawl validators (vld:<requested url> hash etag / last_modified / crawled_at / user_agent)
 pub const CRAWL_LIST_PATH: &str = "cl"; // to be crawled lpush rpop 
 pub const URL_SCORE: &str = "cs"; // Track url backlink score
 pub const DOMAIN_SCORE: &str = "dscr"; // Track domain backlink score
+pub const ROBOTS_CACHE: &str = "rbt"; // Cached robots.txt rules per origin
+pub const SKIPPED: &str = "skp"; // Urls skipped before fetching, with reason
+pub const HOST_COOLDOWN: &str = "hnx"; // Per-host politeness slot
+pub const DEFERRED: &str = "dfr"; // Urls waiting for their host's cooldown
+pub const VALIDATORS: &str = "vld"; // ETag / Last-Modified per crawled url
+pub const ATTEMPTS: &str = "att"; // Failed fetch attempts per url
+pub const SITEMAP_DONE: &str = "smp"; // Origins whose sitemaps were ingested recently
+pub const FEEDS: &str = "fd"; // Discovered RSS / Atom feeds
+pub const FEED_QUEUE: &str = "fdq"; // When each feed is polled next
+pub const WARC_INDEX: &str = "wi"; // Where each archived capture of a url lives
+pub const REINDEX_CHECKPOINT: &str = "rix"; // How far a reindex got in each WARC file
+pub const REINDEX_DOCS: &str = "rixd"; // Document urls a reindex already ingested
+pub const PROTECTION: &str = "prot"; // How often a domain serves challenges, login walls, paywalls
+pub const CANONICAL_ALIAS: &str = "cnl"; // Non-canonical urls and the canonical they were folded into
+pub const SIMHASH_BANDS: &str = "shb"; // Fingerprints by band value, for near-duplicate lookup
+pub const SIMHASH_CLUSTER: &str = "shc"; // Near-duplicate cluster of each fingerprint
+pub const SOFT_404: &str = "s4"; // Soft-404 pages dropped per host
+pub const SOFT_404_PROBE: &str = "s4p"; // Each origin's learned error page
+pub const BUDGET_USAGE: &str = "bg"; // What each domain has used of its budget
+pub const BUDGET_DAILY: &str = "bgd"; // Pages per domain today
+pub const DEPTH: &str = "dpt"; // Link hops from a seed for each queued url
+pub const DOCUMENT_OWNER: &str = "own"; // Which requested url indexes each final / canonical url
+
+// Counters
+pub const STATS_TRUNCATED: &str = "stats:truncated"; // Pages cut at MAX_BODY_BYTES
+pub const STATS_FETCH_ERRORS: &str = "stats:fetch_err"; // Failed fetches per error class (stats:fetch_err:<kind>)
 
 // Filters
 pub const CRAWL_SEEN: &str = "crawl_seen"; // Track recently crawled
@@ -9,17 +9,22 @@ fn main() {
 
     dotenv().ok();
 
+    // Fetches are async (pooled reqwest client), so the default worker count is enough
     let runtime = Builder::new_multi_thread()
-        .worker_threads(32)
-        .max_blocking_threads(512)
         .enable_all() // enables time, I/O, etc.
         .build()
         .unwrap();
 
     runtime.block_on(async {
-        println!("✅ Tokio runtime ready with 32 async threads + 512 blocking threads");
+        println!("✅ Tokio runtime ready");
 
-        crawler::core::traverse().await;
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        match args.first().map(String::as_str) {
+            Some("reindex") => crawler::reindex::run(&args[1..]).await,
+            Some("import-warc") => crawler::import::run(&args[1..]).await,
+            Some("budgets") => crawler::budget::report(),
+            _ => crawler::core::traverse().await,
+        }
     });
 
     // crawler::core::traverse();